@@ -1,5 +1,43 @@
 # Re-export from native module
-from .path_jail import Jail, join
+from .path_jail import (
+    AppendWriter,
+    DecompressedReader,
+    FileTooLargeError,
+    IntegrityError,
+    Jail,
+    JsonlReader,
+    LockedFile,
+    OutsideSubtreeError,
+    ProtectedPathError,
+    QuotaExceededError,
+    QuotaWriter,
+    RandomAccessReader,
+    RecordReader,
+    RevalidatingPath,
+    ShardedCounter,
+    StaleFileError,
+    TenantJail,
+    join,
+)
 
-__all__ = ["Jail", "join"]
+__all__ = [
+    "AppendWriter",
+    "DecompressedReader",
+    "FileTooLargeError",
+    "IntegrityError",
+    "Jail",
+    "JsonlReader",
+    "LockedFile",
+    "OutsideSubtreeError",
+    "ProtectedPathError",
+    "QuotaExceededError",
+    "QuotaWriter",
+    "RandomAccessReader",
+    "RecordReader",
+    "RevalidatingPath",
+    "ShardedCounter",
+    "StaleFileError",
+    "TenantJail",
+    "join",
+]
 __version__ = "0.1.0"
@@ -1,10 +1,255 @@
 """Type stubs for path_jail."""
 
+from collections.abc import Callable, Iterable, Iterator, Set
+from io import BufferedWriter
 from os import PathLike
+from types import TracebackType
+from typing import IO, Any, TypeVar
 
 __version__: str
 
 _PathLike = str | PathLike[str]
+_T = TypeVar("_T")
+
+class OutsideSubtreeError(ValueError):
+    """Path is inside the jail but outside the required subdirectory."""
+
+class IntegrityError(ValueError):
+    """File contents do not match the expected digest."""
+
+class FileTooLargeError(ValueError):
+    """Existing file exceeds the allowed size."""
+
+class QuotaExceededError(ValueError):
+    """Write would exceed the allowed number of bytes."""
+
+class StaleFileError(ValueError):
+    """Existing file is older than the allowed age."""
+
+class ProtectedPathError(ValueError):
+    """Path is inside a protected subtree of the jail."""
+
+class LockedFile:
+    """A file opened by ``Jail.open_locked``, holding an advisory lock.
+
+    Use as a context manager: entering returns the file object, and exiting
+    releases the lock and closes the file.
+    """
+
+    @property
+    def file(self) -> IO[Any]:
+        """The underlying file object."""
+        ...
+
+    def close(self) -> None:
+        """Release the lock and close the file. Safe to call more than once."""
+        ...
+
+    def __enter__(self) -> IO[Any]: ...
+    def __exit__(
+        self,
+        exc_type: type[BaseException] | None,
+        exc: BaseException | None,
+        tb: TracebackType | None,
+    ) -> bool: ...
+
+class RecordReader(Iterator[bytes]):
+    """Iterator over fixed-size records of a file, from ``Jail.read_records``."""
+
+    def __iter__(self) -> RecordReader: ...
+    def __next__(self) -> bytes: ...
+
+class JsonlReader(Iterator[Any]):
+    """Iterator over the records of a JSON Lines file, from ``Jail.read_jsonl``."""
+
+    def __iter__(self) -> JsonlReader: ...
+    def __next__(self) -> Any: ...
+
+class RandomAccessReader:
+    """A file opened for positional reads, from ``Jail.open_ra``."""
+
+    def read_at(self, offset: int, length: int) -> bytes:
+        """Read up to ``length`` bytes starting at ``offset``.
+
+        Uses positional reads (``pread`` on Unix), so the result is short only
+        at end of file and concurrent calls do not interfere.
+        """
+        ...
+
+class DecompressedReader:
+    """A decompressing reader over a file, from ``Jail.open_decompressed``."""
+
+    def read(self, size: int = -1) -> bytes:
+        """Read up to ``size`` decompressed bytes, or everything left if negative.
+
+        Returns fewer than ``size`` bytes only at the end of the data.
+        """
+        ...
+
+    def close(self) -> None:
+        """Close the underlying file. Safe to call more than once."""
+        ...
+
+    def __enter__(self) -> DecompressedReader: ...
+    def __exit__(
+        self,
+        exc_type: type[BaseException] | None,
+        exc: BaseException | None,
+        tb: TracebackType | None,
+    ) -> bool: ...
+
+class QuotaWriter:
+    """A streaming writer with a byte quota, from ``Jail.quota_writer``.
+
+    Closing renames the data into place; exiting a ``with`` block because of
+    an exception discards it instead.
+    """
+
+    @property
+    def written(self) -> int:
+        """Number of bytes written so far."""
+        ...
+
+    def write(self, data: bytes | bytearray | memoryview) -> int:
+        """Write bytes, raising QuotaExceededError if the quota would be exceeded.
+
+        On QuotaExceededError the partial file is removed and the writer is
+        closed.
+        """
+        ...
+
+    def close(self) -> None:
+        """Rename the written data into place. Safe to call more than once."""
+        ...
+
+    def abort(self) -> None:
+        """Discard the written data, leaving the destination untouched."""
+        ...
+
+    def __enter__(self) -> QuotaWriter: ...
+    def __exit__(
+        self,
+        exc_type: type[BaseException] | None,
+        exc: BaseException | None,
+        tb: TracebackType | None,
+    ) -> bool: ...
+
+class AppendWriter:
+    """A buffered appender with periodic flushes, from ``Jail.append_writer``."""
+
+    def write(self, data: str | bytes | bytearray | memoryview) -> int:
+        """Buffer str or bytes, flushing every ``flush_every`` writes or 64 KiB."""
+        ...
+
+    def flush(self) -> None:
+        """Append all buffered data to the file now."""
+        ...
+
+    def close(self) -> None:
+        """Flush remaining data and close the file. Safe to call more than once."""
+        ...
+
+    def __enter__(self) -> AppendWriter: ...
+    def __exit__(
+        self,
+        exc_type: type[BaseException] | None,
+        exc: BaseException | None,
+        tb: TracebackType | None,
+    ) -> bool: ...
+
+class RevalidatingPath:
+    """A path re-validated before every access, from ``Jail.open_revalidating``."""
+
+    @property
+    def path(self) -> str:
+        """The absolute path the accessor currently resolves to."""
+        ...
+
+    def read(self) -> bytes:
+        """Re-validate the path and read the whole file."""
+        ...
+
+    def write(self, data: bytes | bytearray | memoryview) -> None:
+        """Re-validate the path and atomically replace the file with ``data``."""
+        ...
+
+    def append(self, data: bytes | bytearray | memoryview) -> None:
+        """Re-validate the path and append ``data``, creating the file if missing."""
+        ...
+
+class ShardedCounter:
+    """A counter spread over several files, from ``Jail.sharded_counter``."""
+
+    def incr(self, amount: int = 1) -> None:
+        """Add ``amount`` to a pseudo-randomly chosen shard.
+
+        Starting from a random shard, takes the first one whose lock is free,
+        waiting on the starting shard only if every shard is busy. The GIL is
+        released while locking and writing.
+        """
+        ...
+
+    def total(self) -> int:
+        """Sum every shard, reading each under a shared lock.
+
+        Missing shards count as 0.
+        """
+        ...
+
+class TenantJail:
+    """A view of a jail scoped to ``tenants/{tenant_id}/``, from ``Jail.for_tenant``."""
+
+    @property
+    def tenant_id(self) -> str:
+        """The tenant identifier."""
+        ...
+
+    @property
+    def root(self) -> str:
+        """The absolute path of the tenant directory."""
+        ...
+
+    def join(self, path: _PathLike) -> str:
+        """Safely join a path relative to the tenant directory.
+
+        Args:
+            path: Path relative to the tenant directory
+
+        Returns:
+            Absolute path inside the tenant directory
+
+        Raises:
+            ValueError: If path would escape the tenant directory
+        """
+        ...
+
+    def contains(self, path: _PathLike) -> str:
+        """Verify an absolute path is inside the tenant directory.
+
+        Args:
+            path: Absolute path to verify (must exist)
+
+        Returns:
+            Canonicalized path if inside the tenant directory
+
+        Raises:
+            ValueError: If path is outside the tenant directory or not absolute
+        """
+        ...
+
+    def relative(self, path: _PathLike) -> str:
+        """Get the path relative to the tenant directory of an absolute path.
+
+        Args:
+            path: Absolute path inside the tenant directory (must exist)
+
+        Returns:
+            Relative path from the tenant directory
+
+        Raises:
+            ValueError: If path is outside the tenant directory
+        """
+        ...
 
 class Jail:
     """A filesystem sandbox that restricts paths to a root directory.
@@ -18,14 +263,48 @@ class Jail:
         ``^C:\\\\`` that assume a specific format.
     """
 
-    def __init__(self, root: _PathLike) -> None:
+    def __init__(
+        self,
+        root: _PathLike,
+        protected: Iterable[_PathLike] | None = None,
+        require_writable: bool = False,
+        redaction: str = "basename",
+    ) -> None:
         """Create a jail rooted at the given directory.
 
         Args:
             root: Path to the jail root directory (must exist)
+            protected: Relative paths of subtrees that the jail's mutating
+                helpers must never modify (they need not exist yet)
+            require_writable: If True, create and delete a throwaway file in
+                the root to check that the process can write there
+            redaction: How ``redact`` shortens paths for client-facing messages:
+                "basename", "hash" or "relative" (default: "basename")
 
         Raises:
             OSError: If root does not exist or is not a directory
+            PermissionError: If require_writable is set and the root is not
+                writable
+            ValueError: If a protected path would escape the jail, or
+                redaction is not recognized
+        """
+        ...
+
+    def is_protected(self, path: _PathLike) -> bool:
+        """Check whether a path is inside a protected subtree.
+
+        Comparison is component-wise on the validated path, so ``_system2`` is
+        not inside ``_system``, and a symlink into a protected subtree counts
+        as protected.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            True if the jail's mutating helpers would refuse to modify path
+
+        Raises:
+            ValueError: If path would escape the jail
         """
         ...
 
@@ -38,11 +317,22 @@ class Jail:
         """
         ...
 
-    def join(self, path: _PathLike) -> str:
+    def canonical_root(self) -> str:
+        """Return the fully symlink-resolved root used for containment checks.
+
+        Unlike ``root``, this is never shortened for display (on Windows it
+        keeps any ``\\\\?\\`` prefix), so it is exactly the prefix every resolved
+        path is compared against. When the root was given through a symlinked
+        ancestor (macOS ``/tmp`` is ``/private/tmp``), this is the resolved form.
+        """
+        ...
+
+    def join(self, path: _PathLike, within: _PathLike | None = None) -> str:
         """Safely join a relative path to the jail root.
 
         Args:
             path: Relative path to join
+            within: Optional relative subdirectory the result must stay under
 
         Returns:
             Absolute path inside the jail. On Windows, may include ``\\\\?\\``
@@ -50,6 +340,91 @@ class Jail:
 
         Raises:
             ValueError: If path would escape the jail or is absolute
+            OutsideSubtreeError: If path is inside the jail but not under ``within``
+        """
+        ...
+
+    def join_fresh(self, path: _PathLike, max_age_seconds: float) -> str:
+        """Join a relative path to an existing file that must be recent.
+
+        The file's age is measured from its creation time where the platform
+        and filesystem record one, and from its modification time otherwise.
+        Useful for single-use tokens and session files that expire.
+
+        Args:
+            path: Relative path to join (must exist)
+            max_age_seconds: Maximum allowed age in seconds
+
+        Returns:
+            Absolute path inside the jail
+
+        Raises:
+            ValueError: If path would escape the jail or is absolute
+            StaleFileError: If the file is older than ``max_age_seconds``
+            FileNotFoundError: If the file does not exist
+        """
+        ...
+
+    def join_or(self, path: _PathLike, default: _T) -> str | _T:
+        """Safely join a path, returning a fallback instead of raising on bad input.
+
+        The check is purely lexical and never touches the filesystem: ``.`` and
+        ``..`` are collapsed as written, and a path that climbs above the root,
+        is absolute, or contains a null byte returns ``default``. Symlinks are
+        not resolved, so a symlink inside the jail can still lead outside
+        it; use ``join`` when the jail may contain untrusted symlinks.
+
+        Args:
+            path: Relative path to join
+            default: Value to return if path is rejected
+
+        Returns:
+            Absolute path inside the jail, or default
+
+        Raises:
+            TypeError: If path is not a str or PathLike
+        """
+        ...
+
+    def join_with_limit(self, path: _PathLike, max_bytes: int) -> str:
+        """Join a relative path and enforce a size limit on any existing file.
+
+        If the path already exists as a file, its size must not exceed
+        ``max_bytes``. Paths that do not exist yet are returned as with ``join``.
+
+        Args:
+            path: Relative path to join
+            max_bytes: Maximum allowed size of an existing file
+
+        Returns:
+            Absolute path inside the jail
+
+        Raises:
+            ValueError: If path would escape the jail or is absolute
+            FileTooLargeError: If the existing file is larger than ``max_bytes``
+        """
+        ...
+
+    def join_ci(self, path: _PathLike) -> str:
+        """Safely join a path, matching each component case-insensitively.
+
+        After the lexical containment check, every component is looked up in
+        its parent directory: an exact match wins, otherwise the directory is
+        scanned for a unique entry equal ignoring case. Each matched prefix is
+        re-validated, so a symlink cannot lead the scan outside the jail.
+        This is much more expensive than ``join``, and every component must
+        exist. The GIL is released during the scans.
+
+        Args:
+            path: Relative path to join
+
+        Returns:
+            Absolute path inside the jail, with the on-disk spelling
+
+        Raises:
+            ValueError: If path would escape the jail, or a component matches
+                more than one entry ignoring case
+            FileNotFoundError: If a component matches no entry
         """
         ...
 
@@ -82,6 +457,1630 @@ class Jail:
         """
         ...
 
+    def for_tenant(self, tenant_id: str) -> TenantJail:
+        """Get a view of the jail scoped to one tenant's directory.
+
+        Paths passed to the returned TenantJail are joined under
+        ``tenants/{tenant_id}/``, so handlers cannot forget the prefix. The
+        tenant directory does not need to exist yet.
+
+        Args:
+            tenant_id: Tenant identifier, used as a single path component
+
+        Returns:
+            A TenantJail rooted at the tenant's directory
+
+        Raises:
+            ValueError: If tenant_id is empty, "." or "..", or contains a path
+                separator or NUL
+        """
+        ...
+
+    def canonical_relative(self, path: _PathLike) -> str:
+        """Get the symlink-free relative path of an existing file inside the jail.
+
+        Every component is resolved on disk, so two aliases of the same file
+        (e.g. reached through different symlinks) produce the same result.
+        Unlike ``relative``, the path must exist: no lexical, unresolved tail
+        is ever returned, making this suitable as a deduplication key.
+
+        Args:
+            path: Relative path, or absolute path inside the jail (must exist)
+
+        Returns:
+            Physical relative path from the jail root
+
+        Raises:
+            ValueError: If path (or its symlink target) is outside the jail
+            OSError: If the path does not exist
+        """
+        ...
+
+    def build_tree(self, paths: Iterable[_PathLike]) -> dict[str, Any]:
+        """Validate relative paths and arrange them as a nested directory tree.
+
+        Every path is validated against the jail before the tree is built,
+        and the tree uses the validated (normalized) relative form. This is
+        pure structure: entries that are a prefix of another path become
+        nested dicts, and all other entries become leaves.
+
+        Args:
+            paths: Iterable of relative paths
+
+        Returns:
+            Nested dict mapping names to sub-dicts (directories) or None (leaves)
+
+        Raises:
+            ValueError: If any path would escape the jail
+        """
+        ...
+
+    def group_by_dir(self, paths: Iterable[_PathLike]) -> dict[str, list[str]]:
+        """Validate relative paths and group their leaf names by parent directory.
+
+        Grouping uses the validated (normalized) relative form, so ``a/./x``
+        and ``a/y`` land in the same bucket. Directories appear in the order
+        first seen, and names keep their input order. Files directly in the
+        jail root are grouped under ".".
+
+        Args:
+            paths: Iterable of relative paths
+
+        Returns:
+            Dict mapping each parent directory (relative) to a list of names
+
+        Raises:
+            ValueError: If any path would escape the jail or is the jail root
+        """
+        ...
+
+    def dedup_map(
+        self,
+        paths: Iterable[_PathLike],
+        *,
+        case_insensitive: bool = False,
+        unicode_form: str | None = None,
+        strict: bool = True,
+    ) -> dict[_PathLike, str | None]:
+        """Map a batch of paths to canonical keys, revealing aliases.
+
+        Each path is validated and resolved (symlinks followed) in one pass
+        with the GIL released, then reduced to its ``/``-separated relative
+        key. Inputs that reach the same file through different spellings or
+        links get the same key.
+
+        Args:
+            paths: Iterable of relative paths
+            case_insensitive: Lowercase keys, for case-insensitive storage
+                (default: False)
+            unicode_form: Normalize keys to "NFC", "NFD", "NFKC" or "NFKD"
+                (default: None, no normalization)
+            strict: Raise on an escaping path instead of mapping it to None
+                (default: True)
+
+        Returns:
+            Dict mapping each input to its canonical key (or None)
+
+        Raises:
+            ValueError: If a path would escape the jail (when strict), or
+                unicode_form is not recognized
+        """
+        ...
+
+    def ancestors_status(self, path: _PathLike) -> list[tuple[str, bool]]:
+        """List the ancestors of a path inside the jail with existence flags.
+
+        Covers each directory level from just below the jail root down to the
+        path's parent, so a provisioning step can create exactly the missing
+        levels. Stats stop at the first missing level, since everything below
+        it is missing too.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            List of (relpath, exists) tuples, shallowest first, where
+                exists is True only for an existing directory
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def split_existing(self, path: _PathLike) -> tuple[str, str]:
+        """Split a path inside the jail into its existing and missing parts.
+
+        The first part is the deepest ancestor (or the path itself) that
+        exists; the second is the components below it that do not, ready for
+        a single ``os.makedirs(os.path.join(jail.join(prefix), suffix))``.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            Tuple of (existing_prefix, missing_suffix), where the prefix is "."
+                for the jail root and the suffix is "" if path exists
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def path_id(
+        self,
+        path: _PathLike,
+        *,
+        case_insensitive: bool = False,
+        unicode_form: str | None = None,
+    ) -> int:
+        """Compute a stable 64-bit identifier for a path inside the jail.
+
+        The path is validated and resolved like ``dedup_map``, and the id is
+        the first 8 bytes (big-endian) of the SHA-256 digest of its UTF-8
+        ``/``-separated relative key. It is the same across processes and
+        platforms for the same key. It is not a cryptographic commitment:
+        64 bits allow deliberate collisions, so do not use it as an access
+        control token.
+
+        Args:
+            path: Relative path inside the jail
+            case_insensitive: Lowercase the key first (default: False)
+            unicode_form: Normalize the key to "NFC", "NFD", "NFKC" or "NFKD"
+                first (default: None, no normalization)
+
+        Returns:
+            Unsigned 64-bit integer
+
+        Raises:
+            ValueError: If path would escape the jail, or unicode_form is not
+                recognized
+        """
+        ...
+
+    def relative_of_absolute(self, abs_path: _PathLike, resolve: bool = False) -> str:
+        """Get the relative path of an absolute path, optionally without
+        touching the filesystem.
+
+        With ``resolve=False``, the input is normalized lexically (``.`` and
+        ``..`` are collapsed, symlinks are not resolved) and the jail root is
+        stripped from it. The path does not need to exist. The input must use
+        the same form as ``root`` (e.g. ``/private/var`` rather than ``/var`` on
+        macOS). With ``resolve=True``, this behaves like ``relative``.
+
+        Args:
+            abs_path: Absolute path believed to be inside the jail
+            resolve: Resolve symlinks on disk (path must exist)
+
+        Returns:
+            Relative path from the jail root
+
+        Raises:
+            ValueError: If the path is not absolute or is outside the jail
+        """
+        ...
+
+    def resolution_delta(self, path: _PathLike) -> tuple[str, str]:
+        """Compare the lexical and physical resolution of a path.
+
+        The lexical form collapses ``.`` and ``..`` without touching the
+        filesystem; the physical form resolves symlinks like ``join``. If the
+        two differ, symlinks redirected the path.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            Tuple of (lexical, physical) paths relative to the jail root
+
+        Raises:
+            ValueError: If either interpretation escapes the jail
+        """
+        ...
+
+    def write_would_escape(self, path: _PathLike) -> bool:
+        """Check whether writing to a path would modify a file outside the jail.
+
+        The path must stay inside the jail lexically; this then reports
+        whether a symlink on the way (including the final component) leads
+        outside, so a naive ``open(jail.root + path, "w")`` would write through
+        it. A dangling symlink counts when its target lies outside the jail.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            True if a write would land outside the jail
+
+        Raises:
+            ValueError: If path escapes the jail lexically or is invalid
+        """
+        ...
+
+    def listdir_ext(
+        self, path: _PathLike | None = None, *, extensions: Set[str]
+    ) -> list[str]:
+        """List entries of a directory inside the jail with given extensions.
+
+        Extensions are compared case-insensitively against the end of each
+        name and may be given with or without the leading ".", so multi-part
+        suffixes such as ".tar.gz" work. Filtering happens during the
+        directory scan, with the GIL released. Only regular files are listed:
+        symlinks count if they lead to a file inside the jail, while links to
+        directories, dangling links and links escaping the jail are skipped.
+
+        Args:
+            path: Relative path to the directory (default: jail root)
+            extensions: Extensions to keep, e.g. ``{".jpg", ".png"}``
+
+        Returns:
+            Sorted list of matching entry names
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If path is not a readable directory
+        """
+        ...
+
+    def display(self, path: _PathLike, max_len: int = 60) -> str:
+        """Format a path inside the jail for display, truncating long paths.
+
+        Returns the jail-relative path with ``/`` separators. If it is longer
+        than ``max_len`` characters, middle components are replaced with
+        ``...``, keeping the file name and as many leading components as fit
+        (``users/.../file.txt``). A file name that does not fit on its own is
+        itself truncated in the middle; with a ``max_len`` under 4 only (part
+        of) the ``...`` is left.
+
+        Args:
+            path: Relative path inside the jail
+            max_len: Maximum length of the result in characters (default: 60)
+
+        Returns:
+            Display string of at most max_len characters
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def redact(self, path: _PathLike) -> str:
+        """Format a path inside the jail for client-facing error messages.
+
+        The path is validated, then shortened by the jail's ``redaction``
+        policy so the message names the file without exposing the layout:
+        "basename" keeps only the final component, "hash" replaces each
+        parent directory with 8 hex digits of its SHA-256, and "relative"
+        keeps the full jail-relative path. The server root is never included.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            Redacted path with ``/`` separators ("." for the jail root)
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def url_segments(self, path: _PathLike) -> list[str]:
+        """Split a path inside the jail into percent-encoded URL segments.
+
+        Each component of the jail-relative path is encoded separately, so a
+        router can assemble resource URLs without re-encoding. Characters
+        other than letters, digits and ``-._~`` are percent-encoded as UTF-8.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            List of encoded segments (empty for the jail root)
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def object_key(self, path: _PathLike, prefix: str = "", encode: bool = False) -> str:
+        """Compute an object-store key (e.g. for S3) for a path inside the jail.
+
+        The key is the jail-relative path with ``/`` separators, after
+        ``prefix``. It never starts with ``/`` and never contains empty, ``.``
+        or ``..`` segments, so it cannot encode a traversal in the mirror.
+        Characters that object stores recommend avoiding (control characters,
+        backslash, and any of {}^%`[]"<>~#|) are rejected, or percent-encoded
+        with ``encode=True``.
+
+        Args:
+            path: Relative path inside the jail
+            prefix: Key prefix, e.g. "backups/host1" (default: "")
+            encode: Percent-encode problematic characters instead of
+                rejecting them (default: False)
+
+        Returns:
+            The object key
+
+        Raises:
+            ValueError: If path would escape the jail or refers to the jail
+                root, prefix has empty, "." or ".." segments, or the key
+                contains a problematic character and encode is False
+        """
+        ...
+
+    def reveal_target(self, path: _PathLike) -> list[str]:
+        """Build the command that reveals a path in the platform's file manager.
+
+        Returns an argument list for ``subprocess.run``: ``explorer /select,``
+        on Windows and ``open -R`` on macOS, which both highlight the entry.
+        Elsewhere, ``xdg-open`` on the containing directory is returned, since
+        freedesktop file managers have no common "select" flag. The path is
+        validated and canonicalized first, so the command can never point
+        outside the jail.
+
+        Args:
+            path: Relative path inside the jail (must exist)
+
+        Returns:
+            Command and arguments as a list of strings
+
+        Raises:
+            ValueError: If path would escape the jail
+            FileNotFoundError: If the path does not exist
+        """
+        ...
+
+    def archive_name(self, path: _PathLike) -> str:
+        """Compute a safe archive member name for a path inside the jail.
+
+        Returns the jail-relative path with ``/`` separators, suitable as a
+        tar or zip entry name. The result is never absolute and never
+        contains ``..``, so archives built from it cannot be zip-slip vectors.
+        A symlink is named by its own path, not its target's, so it can be
+        archived as a link.
+
+        Args:
+            path: Relative path, or absolute path inside the jail (must exist)
+
+        Returns:
+            POSIX-separated relative name
+
+        Raises:
+            ValueError: If path is outside the jail or refers to the jail root
+        """
+        ...
+
+    def link_count(self, path: _PathLike) -> int:
+        """Return the number of hard links to a file inside the jail.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            The file's link count (``st_nlink``)
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+            NotImplementedError: On platforms without link counts (Windows)
+        """
+        ...
+
+    def allocated_size(self, path: _PathLike) -> int:
+        """Return the disk space actually allocated to a file inside the jail.
+
+        This is ``st_blocks * 512``, which for sparse files (VM images,
+        databases) can be far smaller than the logical size reported by
+        ``os.path.getsize``, and for small files is usually larger.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            Allocated size in bytes
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+            NotImplementedError: On platforms without block counts (Windows)
+        """
+        ...
+
+    def owner(self, path: _PathLike) -> str:
+        """Return the name of the user that owns a file inside the jail.
+
+        The uid is resolved through the passwd database (``getpwuid_r``), and
+        lookups are cached for the life of the process, so listing a large
+        directory stays cheap. Symlinks are followed.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            User name, or the numeric uid as a string if it has no entry
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+            NotImplementedError: On platforms without Unix owners (Windows)
+        """
+        ...
+
+    def group(self, path: _PathLike) -> str:
+        """Return the name of the group that owns a file inside the jail.
+
+        The gid is resolved through the group database (``getgrgid_r``), with
+        the same process-wide cache as ``owner``. Symlinks are followed.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            Group name, or the numeric gid as a string if it has no entry
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+            NotImplementedError: On platforms without Unix groups (Windows)
+        """
+        ...
+
+    def line_count(self, path: _PathLike) -> int:
+        """Count the newline bytes in a file inside the jail.
+
+        Scans the file in chunks in Rust with the GIL released, without
+        decoding it. This counts ``\\n`` occurrences, so a last line without a
+        trailing newline is not counted; add one if the file is non-empty and
+        does not end in ``\\n`` when an exact line count matters.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            Number of ``\\n`` bytes in the file
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def is_text(
+        self,
+        path: _PathLike,
+        sample: int = 8192,
+        max_control_ratio: float = 0.3,
+        require_utf8: bool = False,
+    ) -> bool:
+        """Guess whether a file inside the jail is text or binary.
+
+        Reads up to ``sample`` bytes from the start of the file and classifies
+        them the way editors and diff tools do: any NUL byte means binary;
+        otherwise the file is text if at most ``max_control_ratio`` of the
+        sampled bytes are control characters other than common whitespace,
+        and (with ``require_utf8``) the sample decodes as UTF-8. Empty files
+        count as text.
+
+        Args:
+            path: Relative path to the file (must exist)
+            sample: Number of bytes to inspect (default: 8192)
+            max_control_ratio: Largest fraction of control bytes a text file
+                may contain, from 0.0 to 1.0 (default: 0.3)
+            require_utf8: Also require the sample to be valid UTF-8, which
+                rejects Latin-1 and similar legacy encodings (default: False)
+
+        Returns:
+            True if the file looks like text
+
+        Raises:
+            ValueError: If path would escape the jail, sample is 0, or
+                max_control_ratio is outside 0.0 to 1.0
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def is_servable(
+        self,
+        path: _PathLike,
+        allow_hidden: bool = False,
+        allow: list[str] | None = None,
+    ) -> tuple[bool, str | None]:
+        """Decide whether a path is safe to serve as a static file.
+
+        Bundles the checks a file server needs before sending bytes: the path
+        validates against the jail, exists, is a regular file, is not hidden
+        (no component starting with "."), and matches one of the allow-globs.
+        Escaping or malformed paths (including null bytes) produce a verdict
+        rather than raising.
+
+        Args:
+            path: Relative path requested by the client
+            allow_hidden: Allow dotfiles and dot-directories (default: False)
+            allow: Glob patterns matched against the ``/``-separated relative
+                path; if given, the path must match at least one. ``*`` stays
+                within one directory; use ``**`` to match across directories
+
+        Returns:
+            Tuple of (servable, reason). reason is None when servable, otherwise
+            one of ``"invalid"``, ``"not_found"``, ``"not_file"``, ``"hidden"``,
+            ``"not_allowed"``
+
+        Raises:
+            TypeError: If path is not a str or PathLike
+            ValueError: If an allow pattern is not a valid glob
+        """
+        ...
+
+    def set_readonly(self, path: _PathLike, readonly: bool = True) -> None:
+        """Set or clear the read-only flag on a file inside the jail.
+
+        On Unix, setting clears all write permission bits and clearing
+        restores the owner write bit only. On Windows, this toggles the
+        read-only file attribute.
+
+        Args:
+            path: Relative path to the file (must exist)
+            readonly: True to make read-only, False to make writable
+
+        Raises:
+            ValueError: If path would escape the jail
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If permissions cannot be changed
+        """
+        ...
+
+    def is_readonly(self, path: _PathLike) -> bool:
+        """Check whether a file inside the jail is read-only.
+
+        On Unix, a file is read-only when it has no write permission bits.
+        On Windows, this reflects the read-only file attribute.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            True if the file is read-only
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+        """
+        ...
+
+    def file_type(self, path: _PathLike, follow_symlinks: bool = False) -> str:
+        """Classify an entry inside the jail with a single stat call.
+
+        Args:
+            path: Relative path to the entry (must exist)
+            follow_symlinks: Report the type of a symlink's target rather than
+                "symlink" (default: False)
+
+        Returns:
+            One of "file", "dir", "symlink", "fifo", "socket", "block",
+            "char" or "other"
+
+        Raises:
+            ValueError: If path (or its symlink target) would escape the jail
+            FileNotFoundError: If the path does not exist
+        """
+        ...
+
+    def created_time(self, path: _PathLike) -> float | None:
+        """Return the creation (birth) time of a file inside the jail.
+
+        Uses ``st_birthtime`` on macOS/BSD, the creation time on Windows, and
+        ``statx`` on Linux. Unlike ``st_ctime``, which is the inode change time
+        on Unix, this is when the file was created.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            Seconds since the Unix epoch, or None if the platform or
+            filesystem does not record creation times
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+        """
+        ...
+
+    def empty_dir(self, path: _PathLike) -> None:
+        """Remove all contents of a directory inside the jail, keeping the directory.
+
+        Unlike ``shutil.rmtree``, the directory itself is left in place (useful
+        for mount points and cache directories). Symlinks are removed without
+        being followed, and every directory is re-verified to be inside the
+        jail before descending. The GIL is released during removal.
+
+        Args:
+            path: Relative path to the directory
+
+        Raises:
+            ValueError: If path (or a directory below it) would escape the jail
+            ProtectedPathError: If path is inside, or contains, a protected subtree
+            OSError: If path is not a directory or an entry cannot be removed
+        """
+        ...
+
+    def replace_dir_contents(self, target: _PathLike, source: _PathLike) -> None:
+        """Atomically publish a directory's new contents from a staging directory.
+
+        ``source`` is built by the caller and then swapped with ``target`` in one
+        rename (``renameat2(RENAME_EXCHANGE)`` on Linux, ``renamex_np`` on macOS),
+        so readers of ``target`` see either the complete old tree or the
+        complete new one. The old contents, left at ``source`` by the swap, are
+        then removed. Where the filesystem has no exchange primitive, ``target``
+        is renamed aside before ``source`` is renamed into place, leaving a
+        brief window in which ``target`` does not exist (but is never partial).
+        If ``target`` does not exist yet, ``source`` is simply renamed to it. Both
+        must be on the same filesystem. The GIL is released throughout.
+
+        Args:
+            target: Relative path to the directory to replace
+            source: Relative path to the staging directory with the new contents
+
+        Raises:
+            ValueError: If either path would escape the jail, is the jail root,
+                or one is inside the other
+            ProtectedPathError: If either path is inside, or contains, a protected
+                subtree
+            OSError: If source or target is not a directory, or the swap fails
+        """
+        ...
+
+    def trash(self, path: _PathLike, trash_dir: _PathLike = ".trash") -> str:
+        """Move a file or directory into a trash directory inside the jail.
+
+        The entry is renamed (not copied) into ``trash_dir``, which is created
+        if missing, under a name prefixed with the current time so repeated
+        deletions of the same name never collide. A symlink is moved as a link.
+
+        Args:
+            path: Relative path to the entry to trash
+            trash_dir: Relative path to the trash directory (default: ".trash")
+
+        Returns:
+            Relative path of the trashed entry, for use with ``restore``
+
+        Raises:
+            ValueError: If either path would escape the jail, path is the jail
+                root, or trash_dir is inside path
+            ProtectedPathError: If path is inside, or contains, a protected subtree, or
+                trash_dir is protected
+            FileNotFoundError: If path does not exist
+        """
+        ...
+
+    def restore(self, trashed: _PathLike, original: _PathLike) -> None:
+        """Move a trashed entry back to a location inside the jail.
+
+        Missing parent directories of ``original`` are created. An existing
+        entry at ``original`` is never overwritten.
+
+        Args:
+            trashed: Relative path returned by ``trash``
+            original: Relative path to restore the entry to
+
+        Raises:
+            ValueError: If either path would escape the jail
+            ProtectedPathError: If trashed is inside, or contains, a protected subtree, or
+                original is protected
+            FileExistsError: If original already exists
+            FileNotFoundError: If trashed does not exist
+        """
+        ...
+
+    def size_by_extension(self, path: _PathLike | None = None) -> dict[str, int]:
+        """Total the size of files under a directory, grouped by extension.
+
+        Walks the subtree in Rust with the GIL released. Only regular files
+        are counted; symlinks are not followed, so nothing is counted twice,
+        and symlinks escaping the jail are pruned.
+
+        Args:
+            path: Relative path to the directory (default: jail root)
+
+        Returns:
+            Dict mapping lowercased extension (with leading ".", or "" for
+            files without one) to total size in bytes
+
+        Raises:
+            ValueError: If path (or a directory below it) would escape the jail
+            OSError: If path is not a readable directory
+        """
+        ...
+
+    def hardlink_tree(self, src_root: _PathLike, dst_root: _PathLike) -> int:
+        """Recreate a directory tree by hard-linking its files.
+
+        Walks ``src_root`` in Rust with the GIL released. Directories are
+        created under ``dst_root``, regular files are hard-linked so the copy
+        shares storage with the original, and symlinks are recreated with the
+        same target. Symlinks escaping the jail are pruned from the walk, and
+        a recreated symlink whose target would land outside the jail raises.
+        Other file types (FIFOs, sockets, devices) are skipped.
+
+        Args:
+            src_root: Relative path to the source directory
+            dst_root: Relative path to the destination directory (created if
+                missing)
+
+        Returns:
+            Number of files hard-linked
+
+        Raises:
+            ValueError: If either root, or a recreated symlink, would escape
+                the jail
+            ProtectedPathError: If dst_root is inside, or contains, a protected subtree
+            OSError: If a destination entry already exists, or the two roots
+                are on different filesystems
+        """
+        ...
+
+    def manifest(
+        self, path: _PathLike | None = None, algo: str = "sha256"
+    ) -> list[tuple[str, int, float, str]]:
+        """Build a manifest of every regular file under a directory.
+
+        Walks the subtree and hashes each file in Rust with the GIL released.
+        Symlinks are not followed, and symlinks escaping the jail are pruned.
+
+        Args:
+            path: Relative path to the directory (default: jail root)
+            algo: Hash algorithm, "sha256" or "sha512" (default: "sha256")
+
+        Returns:
+            List of (relpath, size, mtime, hash) tuples sorted by relpath,
+            where relpath is relative to ``path`` with ``/`` separators
+
+        Raises:
+            ValueError: If path (or a directory below it) would escape the
+                jail, or algo is not supported
+            OSError: If path is not a readable directory
+        """
+        ...
+
+    def tree_hash(self, path: _PathLike | None = None) -> str:
+        """Compute a single SHA-256 fingerprint of a directory tree.
+
+        Every directory, regular file (relative path, size and content hash)
+        and symlink (relative path and target) below ``path`` contributes, in
+        a canonical order sorted by relative path, so identical trees always
+        produce the same digest and any change flips it. Modification times
+        are not included. Symlinks escaping the jail are pruned. Runs with
+        the GIL released.
+
+        Args:
+            path: Relative path to the directory (default: jail root)
+
+        Returns:
+            Lowercase hex SHA-256 digest
+
+        Raises:
+            ValueError: If path (or a directory below it) would escape the jail
+            OSError: If path is not a readable directory
+        """
+        ...
+
+    def incr(self, path: _PathLike, amount: int = 1) -> int:
+        """Atomically increment an integer counter file inside the jail.
+
+        Opens (or creates) the file, holds an exclusive advisory lock while
+        reading, incrementing and rewriting the value, then releases it.
+        Concurrent callers using ``incr`` never lose updates. An empty or new
+        file counts as 0. The GIL is released while waiting for the lock.
+
+        Args:
+            path: Relative path to the counter file (parent must exist)
+            amount: Value to add (default: 1)
+
+        Returns:
+            The new counter value
+
+        Raises:
+            ValueError: If path would escape the jail, the file does not
+                contain an integer, or the counter would overflow
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If the file cannot be opened, locked or written
+        """
+        ...
+
+    def sharded_counter(self, name: _PathLike, shards: int = 16) -> ShardedCounter:
+        """Get a counter spread over several files, for hot counters.
+
+        The counter lives in the directory ``name`` (created if missing), as
+        one counter file per shard named ``0``, ``1``, ... Each ``incr`` locks
+        just one shard, so concurrent writers rarely wait on each other;
+        ``total`` pays for this by reading every shard. Shard paths are
+        validated here and again on every operation.
+
+        Use the same ``shards`` for a counter every time it is opened: shards
+        beyond the count given are not included in ``total``.
+
+        Args:
+            name: Relative path to the counter directory (parent must exist)
+            shards: Number of shard files (default: 16)
+
+        Returns:
+            A ShardedCounter for the directory
+
+        Raises:
+            ValueError: If name or a shard would escape the jail, or shards is 0
+            ProtectedPathError: If the counter is inside a protected subtree
+            OSError: If the directory cannot be created
+        """
+        ...
+
+    def update(
+        self,
+        path: _PathLike,
+        transform: Callable[[bytes], bytes | bytearray | memoryview],
+        lock: bool = False,
+    ) -> None:
+        """Read, transform and atomically rewrite a file inside the jail.
+
+        ``transform`` is called with the current contents (under the GIL) and
+        must return the new contents as bytes; the result replaces the file
+        via a temporary sibling and rename, so readers never see a partial
+        write. If ``transform`` raises, the file is left unchanged.
+
+        With ``lock=True``, the whole cycle runs under an exclusive advisory
+        lock on a sidecar ``.<name>.lock`` file, so concurrent ``update``
+        calls never lose each other's changes. The sidecar is used because the
+        rename replaces the file itself; it is left in place afterwards.
+
+        Args:
+            path: Relative path to the file (must exist)
+            transform: Callable taking the old bytes and returning new bytes
+            lock: Serialize with other locked updates (default: False)
+
+        Raises:
+            ValueError: If path would escape the jail
+            ProtectedPathError: If path is inside a protected subtree
+            TypeError: If transform does not return a bytes-like object
+            OSError: If the file cannot be read, locked or written
+        """
+        ...
+
+    def transform_file(
+        self,
+        src: _PathLike,
+        dst: _PathLike,
+        transform: Callable[[bytes], bytes | bytearray | memoryview],
+        chunk_size: int = 65536,
+    ) -> int:
+        """Stream a file through a transform into another file inside the jail.
+
+        ``src`` is read in chunks of ``chunk_size`` bytes (only the last may be
+        shorter) and each chunk is passed to ``transform`` under the GIL; the
+        bytes it returns are written to a temporary sibling of ``dst``, which
+        replaces ``dst`` by rename once the whole file has gone through. Reads
+        and writes release the GIL.
+
+        ``transform`` may return more or fewer bytes than it was given,
+        including none, so it can hold back a partial line or block. After
+        the last chunk it is called once more with ``b""``: a stateful
+        transform must return whatever it still holds, and a stateless one
+        just returns ``b""``. An empty ``src`` gets only that final call.
+
+        If ``transform`` raises, ``dst`` is left unchanged. ``src`` and ``dst`` may
+        be the same file, giving an in-place rewrite.
+
+        Args:
+            src: Relative path to the source file (must exist)
+            dst: Relative path to the destination file (parent must exist)
+            transform: Callable taking a chunk of bytes and returning bytes
+            chunk_size: Size of the chunks passed to transform (default: 65536)
+
+        Returns:
+            Number of bytes written to dst
+
+        Raises:
+            ValueError: If either path would escape the jail, or chunk_size is 0
+            ProtectedPathError: If dst is inside a protected subtree
+            TypeError: If transform does not return a bytes-like object
+            OSError: If src cannot be read or dst cannot be written
+        """
+        ...
+
+    def version_token(self, path: _PathLike) -> int:
+        """Return a token that changes whenever a file inside the jail changes.
+
+        The token is derived from the file's modification time and size
+        (plus inode and change time on Unix), so it is cheap to compute and
+        needs no content hashing. Remember it when reading, then pass it to
+        ``update_if_unchanged`` to write only if nobody changed the file since.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            Opaque 64-bit integer token
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be stat'ed
+        """
+        ...
+
+    def update_if_unchanged(
+        self, path: _PathLike, token: int, data: bytes | bytearray | memoryview
+    ) -> bool:
+        """Atomically replace a file inside the jail if its version token matches.
+
+        The check and the write run under the same sidecar lock as
+        ``update(lock=True)``, so concurrent compare-and-swap updates never
+        overwrite each other. Writers that do not take the lock can still
+        race with it.
+
+        Args:
+            path: Relative path to the file (must exist)
+            token: Token previously returned by ``version_token``
+            data: New contents, as a bytes-like object
+
+        Returns:
+            True if the file was written, False if its token no longer matched
+
+        Raises:
+            ValueError: If path would escape the jail
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If the file cannot be stat'ed, locked or written
+        """
+        ...
+
+    def tail(
+        self,
+        path: _PathLike,
+        lines: int = 100,
+        encoding: str = "utf-8",
+        errors: str = "replace",
+    ) -> list[str]:
+        """Return the last lines of a text file inside the jail.
+
+        Reads backwards from the end in chunks, so only the tail of the file
+        is read, with the GIL released. Lines are split on ``"\\n"`` (a
+        preceding ``"\\r"`` is removed) and returned without line endings.
+
+        Args:
+            path: Relative path to the file
+            lines: Maximum number of lines to return (default: 100)
+            encoding: Text encoding (default: "utf-8"); newline must be a
+                single code unit, as in UTF-8, Latin-1, UTF-16 and UTF-32
+            errors: Decoding error handler, as for ``bytes.decode``
+                (default: "replace")
+
+        Returns:
+            List of up to ``lines`` lines, oldest first
+
+        Raises:
+            ValueError: If path would escape the jail
+            LookupError: If the encoding is unknown
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def is_empty(self, path: _PathLike) -> bool:
+        """Check whether a file or directory inside the jail is empty.
+
+        A file is empty when it has zero length; a directory is empty when it
+        has no entries. Directory checks stop at the first entry found.
+
+        Args:
+            path: Relative path to a file or directory (must exist)
+
+        Returns:
+            True if the file or directory is empty
+
+        Raises:
+            ValueError: If path would escape the jail or is neither a regular
+                file nor a directory
+            OSError: If the path cannot be read
+        """
+        ...
+
+    def entry_count(self, path: _PathLike | None = None) -> int:
+        """Count the entries of a directory inside the jail.
+
+        Entries are counted while iterating the directory, with the GIL
+        released, without building a list of names. Subdirectories are not
+        descended into.
+
+        Args:
+            path: Relative path to the directory (default: jail root)
+
+        Returns:
+            Number of entries, excluding "." and ".."
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If path is not a readable directory
+        """
+        ...
+
+    def list_tree(self, path: _PathLike, max_depth: int = 1) -> list[str]:
+        """List a subtree inside the jail, at most ``max_depth`` levels deep.
+
+        Depth counts from ``path``, not from the jail root: ``max_depth=1`` lists
+        ``path`` itself, and higher values pre-fetch deeper levels for a tree
+        view. Symlinks are listed but never descended into, and symlinks that
+        leave the jail (or are broken) are left out. The GIL is released
+        during the walk.
+
+        Args:
+            path: Relative path to the directory
+            max_depth: Number of levels to list (default: 1)
+
+        Returns:
+            Paths relative to the jail root, depth-first, sorted by name
+            within each directory
+
+        Raises:
+            ValueError: If path (or a directory below it) would escape the jail
+            OSError: If path is not a readable directory
+        """
+        ...
+
+    def read_verified(self, path: _PathLike, expected_sha256: str) -> bytes:
+        """Read a file inside the jail and verify its SHA-256 digest.
+
+        The digest is computed while reading (with the GIL released), and the
+        contents are only returned if it matches, so corrupted or tampered
+        data never reaches the caller.
+
+        Args:
+            path: Relative path to the file
+            expected_sha256: Expected digest as 64 hex characters
+
+        Returns:
+            The file contents
+
+        Raises:
+            ValueError: If path would escape the jail or the digest is malformed
+            IntegrityError: If the contents do not match the expected digest
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def create_exclusive(self, path: _PathLike) -> BufferedWriter:
+        """Create a new file inside the jail, failing if it already exists.
+
+        The file is opened with ``O_CREAT | O_EXCL``, so exactly one caller wins
+        a race to create it; there is no check-then-create window. Only the
+        parent is resolved; the final component is used as given, so any
+        existing entry there, including a dangling or escaping symlink,
+        counts as existing and is never followed.
+
+        Args:
+            path: Relative path to the new file (parent must exist)
+
+        Returns:
+            A binary file object open for writing
+
+        Raises:
+            ValueError: If path would escape the jail
+            ProtectedPathError: If path is inside a protected subtree
+            FileExistsError: If the path already exists
+            OSError: If the file cannot be created
+        """
+        ...
+
+    def read_json(self, path: _PathLike) -> Any:
+        """Read and parse a JSON file inside the jail.
+
+        The file is read and parsed with the GIL released. Object key order is
+        preserved and integers of any size are supported, as with ``json.load``.
+
+        Args:
+            path: Relative path to the file
+
+        Returns:
+            The decoded Python object
+
+        Raises:
+            ValueError: If path would escape the jail or the file is not valid
+                JSON (the message includes the byte offset)
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def write_json(self, path: _PathLike, obj: Any, indent: int | None = None) -> None:
+        """Serialize an object as JSON and atomically write it inside the jail.
+
+        The data is written to a temporary file in the same directory and
+        renamed over the target, so readers never see a partial file.
+
+        Args:
+            path: Relative path to the file (parent must exist)
+            obj: JSON-serializable object (dict, list, str, int, float, bool, None)
+            indent: Spaces per indentation level; None for compact output
+
+        Raises:
+            ValueError: If path would escape the jail, or obj contains NaN,
+                infinity or a circular reference
+            RecursionError: If obj is nested more than 127 levels deep
+            ProtectedPathError: If path is inside a protected subtree
+            TypeError: If obj is not JSON serializable
+            OSError: If the file cannot be written
+        """
+        ...
+
+    def read_jsonl(self, path: _PathLike) -> JsonlReader:
+        """Iterate over the records of a JSON Lines file inside the jail.
+
+        Each line is read and parsed with the GIL released, so large files
+        are streamed rather than loaded whole. Blank lines are skipped.
+
+        Args:
+            path: Relative path to the file
+
+        Returns:
+            An iterator yielding one decoded object per line
+
+        Raises:
+            ValueError: If path would escape the jail, or (while iterating) a
+                line is not valid JSON (the message includes the line number)
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def write_jsonl(self, path: _PathLike, objects: Iterable[Any]) -> int:
+        """Append objects to a JSON Lines file inside the jail.
+
+        Every object is serialized before anything is written, so a
+        serialization error writes nothing. The lines are then appended with a
+        single write under an exclusive advisory lock on the file (the GIL is
+        released while waiting), so concurrent ``write_jsonl`` calls never
+        interleave partial lines, however large the records. Writers that do
+        not lock the file get no such guarantee. The file is created if missing.
+
+        Args:
+            path: Relative path to the file (parent must exist)
+            objects: Iterable of JSON-serializable objects
+
+        Returns:
+            Number of records written
+
+        Raises:
+            ValueError: If path would escape the jail, or an object contains
+                NaN, infinity or a circular reference
+            RecursionError: If an object is nested more than 127 levels deep
+            ProtectedPathError: If path is inside a protected subtree
+            TypeError: If an object is not JSON serializable
+            OSError: If the file cannot be written
+        """
+        ...
+
+    def quota_writer(self, path: _PathLike, max_bytes: int) -> QuotaWriter:
+        """Open a streaming writer that enforces a byte quota.
+
+        Data goes to a temporary sibling of ``path`` and is renamed over
+        ``path`` when the writer is closed, so readers never see a partial
+        file. The moment a write would push the total past ``max_bytes``, the
+        temporary file is removed and QuotaExceededError is raised; ``path``
+        is untouched.
+
+        Args:
+            path: Relative path to the destination (parent must exist)
+            max_bytes: Maximum total number of bytes that may be written
+
+        Returns:
+            A QuotaWriter, usable as a context manager
+
+        Raises:
+            ValueError: If path would escape the jail
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If the temporary file cannot be created
+        """
+        ...
+
+    def append_writer(self, path: _PathLike, flush_every: int = 100) -> AppendWriter:
+        """Open a buffered appender for high-throughput logging.
+
+        The path is validated once, at open. Writes are buffered and appended
+        every ``flush_every`` writes (or once 64 KiB is buffered), amortizing
+        syscalls; ``flush()``, ``close()`` and leaving a ``with`` block write out
+        whatever remains. The file is created if missing.
+
+        Args:
+            path: Relative path to the file (parent must exist)
+            flush_every: Number of writes to buffer between flushes
+
+        Returns:
+            An AppendWriter, usable as a context manager
+
+        Raises:
+            ValueError: If path would escape the jail, or flush_every is 0
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If the file cannot be opened
+        """
+        ...
+
+    def append_capped(
+        self,
+        path: _PathLike,
+        data: bytes | bytearray | memoryview,
+        max_bytes: int,
+        keep: int = 3,
+        rotate: str = "before",
+    ) -> bool:
+        """Append to a file inside the jail, rotating it when it grows too big.
+
+        Works like a size-based ``RotatingFileHandler``: when the size cap is
+        hit, ``path`` is renamed to ``path.1``, an existing ``path.1`` to
+        ``path.2``, and so on, deleting whatever falls past ``path.<keep>``.
+        With ``keep=0`` the full file is deleted instead. Backups live in the
+        validated parent directory of ``path`` and are handled by name: a
+        symlink in a backup slot is moved or deleted itself, never followed.
+        ``path`` itself must not be a symlink, so the file, its lock and its
+        backups always sit side by side.
+
+        ``rotate`` sets when the cap is checked:
+
+        - "before" (default): if appending would take a non-empty file past
+          ``max_bytes``, rotate first and write ``data`` to a fresh file. Files
+          stay within the cap unless a single ``data`` is larger than it.
+        - "after": append, then rotate if the file now exceeds ``max_bytes``.
+          Records are never split from the file they fill, but backups can
+          overshoot the cap by one record.
+
+        The size check, rotation and append run under an exclusive advisory
+        lock on a sidecar ``.<name>.lock`` file (as ``update(lock=True)`` uses),
+        so concurrent callers never rotate twice or lose a record.
+
+        Args:
+            path: Relative path to the file (created if missing)
+            data: Bytes to append
+            max_bytes: Size cap for the file, in bytes
+            keep: Number of numbered backups to keep, at most 1000
+                (default: 3)
+            rotate: "before" or "after" (default: "before")
+
+        Returns:
+            True if the file was rotated
+
+        Raises:
+            ValueError: If path would escape the jail, is the jail root or
+                a symlink, max_bytes is 0, keep is over 1000, or rotate is not
+                "before" or "after"
+            ProtectedPathError: If path or a backup is inside a protected subtree
+            OSError: If the file cannot be locked, rotated or written
+        """
+        ...
+
+    def open_locked(
+        self, path: _PathLike, mode: str = "r+b", shared: bool = False
+    ) -> LockedFile:
+        """Open a file inside the jail and hold an advisory lock on it.
+
+        The lock is taken on the same descriptor the returned file uses, and
+        is held until the ``with`` block exits (or ``close()`` is called). The
+        GIL is released while waiting for the lock. Locks are advisory: they
+        only exclude other code that also locks the file.
+
+        Modes that truncate on open (``"w"``) are rejected, since truncation
+        would happen before the lock is held; open with ``"r+"`` and truncate
+        instead.
+
+        Args:
+            path: Relative path to the file
+            mode: File mode, as for ``open()`` (default: "r+b")
+            shared: Take a shared (read) lock instead of an exclusive one
+
+        Returns:
+            A LockedFile context manager yielding the file object
+
+        Raises:
+            ValueError: If path would escape the jail or mode truncates
+            ProtectedPathError: If mode can write and path is inside a protected subtree
+            OSError: If the file cannot be opened or locked
+        """
+        ...
+
+    def is_locked(self, path: _PathLike) -> bool:
+        """Check whether a file inside the jail is currently locked.
+
+        Probes with a non-blocking exclusive lock on a fresh descriptor and
+        releases it immediately if acquired. A shared or exclusive lock held
+        by any process (including this one, through ``open_locked``) counts.
+        This is best-effort: advisory locks only reflect cooperating code,
+        and the answer can be stale as soon as it is returned.
+
+        Args:
+            path: Relative path to the file (must exist)
+
+        Returns:
+            True if another lock on the file prevented the probe
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be opened or probed
+        """
+        ...
+
+    def read_records(
+        self, path: _PathLike, record_size: int, strict: bool = False
+    ) -> RecordReader:
+        """Iterate over fixed-size records of a binary file inside the jail.
+
+        Reads are buffered and done with the GIL released, so large record
+        files are streamed rather than loaded whole.
+
+        Args:
+            path: Relative path to the file
+            record_size: Size of each record in bytes
+            strict: Raise on a partial record at the end of the file instead
+                of yielding it short (default: False)
+
+        Returns:
+            An iterator yielding each record as bytes
+
+        Raises:
+            ValueError: If path would escape the jail, record_size is 0, or
+                (when strict) the file ends with a partial record
+            OSError: If the file cannot be read
+        """
+        ...
+
+    def open_revalidating(self, path: _PathLike) -> RevalidatingPath:
+        """Get an accessor that re-validates a path before every operation.
+
+        Unlike an open file, which stays bound to the file it opened, the
+        accessor re-runs the jail check on the current state of ``path``
+        before each read or write, and refuses to proceed if it now escapes
+        (e.g. a directory was swapped for a symlink). Each operation therefore
+        costs a full path resolution plus an open.
+
+        Args:
+            path: Relative path inside the jail
+
+        Returns:
+            A RevalidatingPath for the path
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def files_differ(self, a: _PathLike, b: _PathLike) -> bool:
+        """Check whether two files inside the jail have different contents.
+
+        Sizes are compared first; equal-sized files are then read in lockstep
+        with the GIL released, stopping at the first differing chunk. Cheaper
+        than hashing both files when only a yes/no answer is needed.
+
+        Args:
+            a: Relative path to the first file
+            b: Relative path to the second file
+
+        Returns:
+            True if the contents differ
+
+        Raises:
+            ValueError: If either path would escape the jail
+            OSError: If either file cannot be read
+        """
+        ...
+
+    def write_if_changed(self, path: _PathLike, data: bytes | bytearray | memoryview) -> bool:
+        """Atomically write a file inside the jail only if its contents differ.
+
+        The existing file is compared with ``data`` first (sizes, then chunks,
+        with the GIL released), and left untouched when they are equal, so
+        re-running a generator does not bump mtimes or wake file watchers.
+        Otherwise the data is written via a temporary sibling and rename.
+
+        Args:
+            path: Relative path to the file (parent must exist)
+            data: New contents, as a bytes-like object
+
+        Returns:
+            True if the file was written, False if it already had this content
+
+        Raises:
+            ValueError: If path would escape the jail
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If the file cannot be read or written
+        """
+        ...
+
+    def write_batch(
+        self, items: Iterable[tuple[_PathLike, bytes | bytearray | memoryview]]
+    ) -> None:
+        """Write several files inside the jail as a unit.
+
+        Every destination is validated before anything is written, so one
+        escaping path fails the whole batch. All data is then staged in
+        temporary siblings and renamed into place; if a rename fails, files
+        already replaced get their previous contents back and files newly
+        created are removed. This covers errors raised during the call, not
+        crashes: if the process dies mid-batch, some files may be updated.
+
+        Args:
+            items: Iterable of (path, data) pairs, where data is bytes-like
+
+        Raises:
+            ValueError: If any path would escape the jail
+            ProtectedPathError: If any path is inside a protected subtree
+            OSError: If a file cannot be written (after rolling back)
+        """
+        ...
+
+    def temp_sibling(self, path: _PathLike) -> str:
+        """Return an unused temporary name next to a path inside the jail.
+
+        The name has the same ``.tmp-<random>-<name>`` form the built-in atomic
+        writers use, in the same directory as ``path`` (so a rename over ``path``
+        is atomic), and no entry by that name exists when it is returned.
+        Nothing is created; open it exclusively (mode "x") to claim it.
+
+        Args:
+            path: Relative path to the eventual target
+
+        Returns:
+            Relative path of the temporary sibling
+
+        Raises:
+            ValueError: If path would escape the jail or is the jail root
+        """
+        ...
+
+    def open_ra(self, path: _PathLike) -> RandomAccessReader:
+        """Open a file inside the jail for positional reads.
+
+        The path is validated once at open. The returned reader has no shared
+        cursor, so several threads can call ``read_at`` on it concurrently.
+
+        Args:
+            path: Relative path to the file
+
+        Returns:
+            A RandomAccessReader for the file
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be opened
+        """
+        ...
+
+    def open_decompressed(self, path: _PathLike) -> tuple[DecompressedReader, str | None]:
+        """Open a possibly compressed file inside the jail for reading.
+
+        The format is sniffed from the magic bytes (gzip, zstd or bzip2), and
+        the returned reader decompresses as it is read, with the GIL released.
+        Files in no recognized format are read unchanged.
+
+        Args:
+            path: Relative path to the file
+
+        Returns:
+            Tuple of (reader, format), where format is "gzip", "zstd",
+                "bzip2" or None for uncompressed data
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If the file cannot be opened, or (while reading) the
+                compressed data is corrupt
+        """
+        ...
+
+    def write_compressed(
+        self,
+        path: _PathLike,
+        data: bytes | bytearray | memoryview | IO[bytes],
+        format: str = "gzip",
+        level: int | None = None,
+    ) -> int:
+        """Compress data and atomically write it to a file inside the jail.
+
+        Compression happens in Rust with the GIL released, into a temporary
+        sibling that is renamed over ``path`` once complete, so readers never
+        see a partial file. ``data`` may be a bytes-like object or a binary
+        file-like object, which is read in chunks so large inputs are never
+        fully buffered. The zstd encoder is pure Rust and only implements
+        level 1, so other zstd levels are rejected.
+
+        Args:
+            path: Relative path to the file (parent must exist)
+            data: Bytes-like object, or file-like object with a ``read`` method
+            format: "gzip", "zstd" or "bzip2" (default: "gzip")
+            level: Compression level: 0-9 for gzip, 1-9 for bzip2, 1 for zstd
+                (default: 6 for gzip and bzip2, 1 for zstd)
+
+        Returns:
+            Size of the compressed file in bytes
+
+        Raises:
+            ValueError: If path would escape the jail, or format or level is
+                not supported
+            ProtectedPathError: If path is inside a protected subtree
+            OSError: If the file cannot be written
+        """
+        ...
+
+    def case_collides(self, path: _PathLike) -> bool:
+        """Check whether a path would collide with an existing entry that differs
+        only in case.
+
+        Reads the parent directory and compares entry names to the final
+        component of ``path`` case-insensitively. An exact match is not a
+        collision. This flags names that coexist on a case-sensitive
+        filesystem but would clash on macOS or Windows.
+
+        Args:
+            path: Relative path whose final component is checked
+
+        Returns:
+            True if another entry matches the name case-insensitively
+
+        Raises:
+            ValueError: If path would escape the jail or has no final component
+            OSError: If the parent directory cannot be read
+        """
+        ...
+
+    def is_ignored(self, path: _PathLike, ignore_files: list[str] | None = None) -> bool:
+        """Check whether a path is ignored by ignore files inside the jail.
+
+        Ignore files (gitignore syntax) are looked up in every directory from
+        the jail root down to the path's parent. Deeper files take precedence,
+        and ignore files that resolve outside the jail are not consulted. As
+        in git, a path under an ignored directory is ignored even if a deeper
+        file re-includes it with ``!``.
+
+        Args:
+            path: Relative path to check
+            ignore_files: Names of ignore files to consult (default: [".gitignore"])
+
+        Returns:
+            True if the path is ignored
+
+        Raises:
+            ValueError: If path would escape the jail
+        """
+        ...
+
+    def matches_ignore(self, path: _PathLike, ignore_spec: Iterable[str]) -> bool:
+        """Check whether a path matches caller-supplied gitignore-style patterns.
+
+        Patterns are interpreted as if they were lines of an ignore file in
+        the jail root (``.dockerignore``, ``.npmignore`` and the like): later
+        patterns override earlier ones, ``!`` negates, a trailing ``/`` matches
+        directories only, and ``**`` spans directories. A path is also ignored
+        when one of its parent directories is.
+
+        Args:
+            path: Relative path to check
+            ignore_spec: Iterable of patterns, one per ignore-file line
+
+        Returns:
+            True if the path is ignored
+
+        Raises:
+            ValueError: If path would escape the jail or a pattern is invalid
+        """
+        ...
+
+    def listdir_grouped(self, path: _PathLike | None = None) -> dict[str, list[str]]:
+        """List a directory inside the jail, grouped by entry type.
+
+        Entries are classified in a single pass using the file type reported
+        by the directory scan. Symlinks are not followed; those whose target
+        resolves outside the jail (or is broken) are also listed under
+        ``"escaping"``. The GIL is released during the scan.
+
+        Args:
+            path: Relative path to the directory (default: jail root)
+
+        Returns:
+            Dict with ``"dirs"``, ``"files"``, ``"symlinks"``, ``"other"`` and
+            ``"escaping"`` keys, each a sorted list of entry names
+
+        Raises:
+            ValueError: If path would escape the jail
+            OSError: If path is not a readable directory
+        """
+        ...
+
     def __repr__(self) -> str: ...
     def __str__(self) -> str: ...
 
@@ -0,0 +1,183 @@
+//! Atomic file replacement via a temporary sibling and rename.
+
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Build a random temporary name in the same directory as `target`.
+///
+/// The name looks like `.tmp-<random>-<file name>`, so it stays on the same
+/// filesystem as the target (making the final rename atomic) and is hidden
+/// from casual listings. The caller must create it with `create_new`.
+pub(crate) fn temp_sibling(target: &Path) -> PathBuf {
+    let random = RandomState::new().build_hasher().finish();
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(".tmp-{:016x}-{}", random, name))
+}
+
+/// Create a new temporary sibling of `target`, retrying on name collisions.
+pub(crate) fn create_temp(target: &Path) -> io::Result<(PathBuf, File)> {
+    loop {
+        let temp = temp_sibling(target);
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Move a fully written temporary file over `target`, removing it on failure.
+pub(crate) fn commit(temp: &Path, file: File, target: &Path) -> io::Result<()> {
+    let synced = file.sync_all();
+    // Close before renaming or removing; Windows refuses either on open files
+    drop(file);
+    let result = synced.and_then(|()| std::fs::rename(temp, target));
+    if result.is_err() {
+        let _ = std::fs::remove_file(temp);
+    }
+    result
+}
+
+/// Atomically replace `target` with `data`.
+///
+/// Readers see either the old contents or the new contents, never a partial
+/// write. The parent directory of `target` must exist.
+pub(crate) fn write(target: &Path, data: &[u8]) -> io::Result<()> {
+    let (temp, mut file) = create_temp(target)?;
+    if let Err(err) = file.write_all(data) {
+        drop(file);
+        let _ = std::fs::remove_file(&temp);
+        return Err(err);
+    }
+    commit(&temp, file, target)
+}
+
+/// Write several files as a unit, rolling back on failure.
+///
+/// Every file's data is first written to a temporary sibling, so most
+/// failures (missing directories, a full disk) happen before any target
+/// changes. The temporaries are then renamed into place one by one, with
+/// each replaced file kept as a hard link (or copy) until the end. If a
+/// rename fails, the targets already replaced are restored and those newly
+/// created are removed, in reverse order. A crash during the rename phase
+/// can still leave the batch partially applied.
+pub(crate) fn write_batch(items: &[(PathBuf, Vec<u8>)]) -> io::Result<()> {
+    let mut staged = Vec::with_capacity(items.len());
+    for (target, data) in items {
+        let result = create_temp(target).and_then(|(temp, mut file)| {
+            if let Err(err) = file.write_all(data).and_then(|()| file.sync_all()) {
+                drop(file);
+                let _ = std::fs::remove_file(&temp);
+                return Err(err);
+            }
+            Ok((temp, file))
+        });
+        match result {
+            Ok(entry) => staged.push(entry),
+            Err(err) => {
+                for (temp, file) in staged {
+                    drop(file);
+                    let _ = std::fs::remove_file(temp);
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    // (target, backup of its previous contents) for each applied write
+    let mut applied: Vec<(&Path, Option<PathBuf>)> = Vec::with_capacity(items.len());
+    let mut staged = staged.into_iter();
+    let mut failure = None;
+    for ((target, _), (temp, file)) in items.iter().zip(staged.by_ref()) {
+        let backup = if target.symlink_metadata().is_ok() {
+            let backup = temp_sibling(target);
+            match std::fs::hard_link(target, &backup)
+                .or_else(|_| std::fs::copy(target, &backup).map(drop))
+            {
+                Ok(()) => Some(backup),
+                Err(err) => {
+                    drop(file);
+                    let _ = std::fs::remove_file(&temp);
+                    failure = Some(err);
+                    break;
+                }
+            }
+        } else {
+            None
+        };
+        match commit(&temp, file, target) {
+            Ok(()) => applied.push((target, backup)),
+            Err(err) => {
+                if let Some(backup) = backup {
+                    let _ = std::fs::remove_file(backup);
+                }
+                failure = Some(err);
+                break;
+            }
+        }
+    }
+
+    let Some(err) = failure else {
+        for (_, backup) in applied {
+            if let Some(backup) = backup {
+                let _ = std::fs::remove_file(backup);
+            }
+        }
+        return Ok(());
+    };
+    for (temp, file) in staged {
+        drop(file);
+        let _ = std::fs::remove_file(temp);
+    }
+    for (target, backup) in applied.into_iter().rev() {
+        let _ = match backup {
+            Some(backup) => std::fs::rename(backup, target),
+            None => std::fs::remove_file(target),
+        };
+    }
+    Err(err)
+}
+
+/// Atomically swap two paths, so each name refers to what the other did.
+///
+/// Uses `renameat2(RENAME_EXCHANGE)` on Linux and `renamex_np(RENAME_SWAP)`
+/// on macOS. Elsewhere, and on filesystems without support, this fails with
+/// `Unsupported` or `InvalidInput` and the caller must fall back.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    // SAFETY: both arguments are valid NUL-terminated strings
+    #[cfg(target_os = "linux")]
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    // SAFETY: both arguments are valid NUL-terminated strings
+    #[cfg(target_os = "macos")]
+    let result = unsafe { libc::renamex_np(a.as_ptr(), b.as_ptr(), libc::RENAME_SWAP) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn exchange(_a: &Path, _b: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
@@ -0,0 +1,175 @@
+//! Compression format detection and streaming (de)compression.
+
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use ruzstd::decoding::StreamingDecoder;
+use ruzstd::encoding::{CompressionLevel, FrameCompressor};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::RangeInclusive;
+
+/// A reader yielding decompressed bytes.
+pub(crate) type Decoder = Box<dyn Read + Send + Sync>;
+
+/// Detect the compression format of `file` from its magic bytes.
+///
+/// Returns the format name ("gzip", "zstd" or "bzip2") and a reader that
+/// decompresses the file as it is read. Data in no recognized format is
+/// passed through unchanged, with `None` as the format.
+pub(crate) fn decoder(file: File) -> io::Result<(Option<&'static str>, Decoder)> {
+    let mut reader = BufReader::new(file);
+    let head = reader.fill_buf()?;
+    let format = if head.starts_with(&[0x1f, 0x8b]) {
+        Some("gzip")
+    } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some("zstd")
+    } else if head.starts_with(b"BZh") {
+        Some("bzip2")
+    } else {
+        None
+    };
+    let decoder: Decoder = match format {
+        Some("gzip") => Box::new(MultiGzDecoder::new(reader)),
+        Some("zstd") => Box::new(
+            StreamingDecoder::new(reader)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?,
+        ),
+        Some(_) => Box::new(MultiBzDecoder::new(reader)),
+        None => Box::new(reader),
+    };
+    Ok((format, decoder))
+}
+
+/// A compression format that [`encode`] can produce.
+#[derive(Clone, Copy)]
+pub(crate) enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Format {
+    /// Look up a format by the name [`decoder`] reports for it.
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Self::Gzip),
+            "zstd" => Some(Self::Zstd),
+            "bzip2" => Some(Self::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// Compression levels accepted for this format. The pure Rust zstd
+    /// encoder only implements level 1, so that is the only one offered
+    /// rather than quietly compressing higher levels the same way.
+    pub(crate) fn levels(self) -> RangeInclusive<u32> {
+        match self {
+            Self::Gzip => 0..=9,
+            Self::Zstd => 1..=1,
+            Self::Bzip2 => 1..=9,
+        }
+    }
+
+    /// Level used when none is given.
+    pub(crate) fn default_level(self) -> u32 {
+        match self {
+            Self::Gzip | Self::Bzip2 => 6,
+            Self::Zstd => 1,
+        }
+    }
+}
+
+/// Compress everything read from `source` into `target`.
+///
+/// `level` must be in `format.levels()`.
+pub(crate) fn encode<R: Read, W: Write>(
+    format: Format,
+    level: u32,
+    mut source: R,
+    target: W,
+) -> io::Result<()> {
+    match format {
+        Format::Gzip => {
+            let mut encoder = GzEncoder::new(target, flate2::Compression::new(level));
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Format::Bzip2 => {
+            let level = bzip2::Compression::try_new(level).unwrap_or_default();
+            let mut encoder = BzEncoder::new(target, level);
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Format::Zstd => {
+            // The compressor panics on I/O errors, so hand it adapters that
+            // swallow the first error and report it afterwards
+            let mut source = Latch::new(source);
+            let mut target = Latch::new(target);
+            let mut compressor = FrameCompressor::new(CompressionLevel::Fastest);
+            compressor.set_source(&mut source);
+            compressor.set_drain(&mut target);
+            compressor.compress();
+            drop(compressor);
+            source.finish()?;
+            target.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Reader/writer adapter that records the first I/O error instead of
+/// returning it: reads then hit end of stream and writes are discarded.
+struct Latch<T> {
+    inner: T,
+    error: Option<io::Error>,
+}
+
+impl<T> Latch<T> {
+    fn new(inner: T) -> Self {
+        Self { inner, error: None }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+impl<R: Read> Read for Latch<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.error.is_some() {
+            return Ok(0);
+        }
+        loop {
+            match self.inner.read(buf) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.error = Some(err);
+                    return Ok(0);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Latch<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.error.is_none() {
+            if let Err(err) = self.inner.write_all(buf) {
+                self.error = Some(err);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.error.is_none() {
+            if let Err(err) = self.inner.flush() {
+                self.error = Some(err);
+            }
+        }
+        Ok(())
+    }
+}
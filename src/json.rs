@@ -0,0 +1,160 @@
+//! Conversion between JSON values and Python objects.
+
+use pyo3::exceptions::{PyRecursionError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Map, Number, Value};
+
+/// Convert a parsed JSON value to the object `json.loads` would return.
+pub(crate) fn to_py<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Value::Number(n) => number_to_py(py, n)?,
+        Value::String(s) => PyString::new(py, s).into_any(),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_py(py, item)?)?;
+            }
+            list.into_any()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, to_py(py, item)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+fn number_to_py<'py>(py: Python<'py>, n: &Number) -> PyResult<Bound<'py, PyAny>> {
+    if let Some(i) = n.as_i64() {
+        return Ok(i.into_pyobject(py)?.into_any());
+    }
+    if let Some(u) = n.as_u64() {
+        return Ok(u.into_pyobject(py)?.into_any());
+    }
+    let text = n.to_string();
+    if text.contains(['.', 'e', 'E']) {
+        Ok(PyFloat::new(py, n.as_f64().unwrap_or(f64::NAN)).into_any())
+    } else {
+        // Integers beyond 64 bits, as Python's json module supports
+        py.get_type::<PyInt>().call1((text,))
+    }
+}
+
+/// Deepest nesting of lists and dicts `from_py` accepts. This is the most
+/// serde_json will parse, so anything written can be read back, and keeps
+/// the recursion well clear of the native stack limit.
+const MAX_DEPTH: usize = 127;
+
+/// Convert a Python object to a JSON value, as `json.dumps` would accept.
+///
+/// NaN and infinity are rejected because they are not valid JSON. A list or
+/// dict that contains itself raises ValueError, and nesting deeper than
+/// `MAX_DEPTH` raises RecursionError, as `json.dumps` does.
+pub(crate) fn from_py(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    convert(obj, &mut Vec::new())
+}
+
+/// `from_py`, with the ids of the containers currently being converted.
+fn convert(obj: &Bound<'_, PyAny>, stack: &mut Vec<usize>) -> PyResult<Value> {
+    if obj.is_none() {
+        return Ok(Value::Null);
+    }
+    // bool is a subclass of int, so check it first
+    if let Ok(b) = obj.downcast::<PyBool>() {
+        return Ok(Value::Bool(b.is_true()));
+    }
+    if obj.is_instance_of::<PyInt>() {
+        let text = obj.str()?.to_cow()?.into_owned();
+        return text
+            .parse::<Number>()
+            .map(Value::Number)
+            .map_err(|e| PyValueError::new_err(format!("cannot encode integer: {}", e)));
+    }
+    if let Ok(f) = obj.downcast::<PyFloat>() {
+        return Number::from_f64(f.value())
+            .map(Value::Number)
+            .ok_or_else(|| PyValueError::new_err("NaN and infinity are not valid JSON"));
+    }
+    if let Ok(s) = obj.downcast::<PyString>() {
+        return Ok(Value::String(s.to_cow()?.into_owned()));
+    }
+    let is_array = obj.is_instance_of::<PyList>() || obj.is_instance_of::<PyTuple>();
+    if is_array || obj.is_instance_of::<PyDict>() {
+        let id = obj.as_ptr() as usize;
+        if stack.contains(&id) {
+            return Err(PyValueError::new_err("Circular reference detected"));
+        }
+        if stack.len() >= MAX_DEPTH {
+            return Err(PyRecursionError::new_err(format!(
+                "JSON nesting deeper than {} levels",
+                MAX_DEPTH
+            )));
+        }
+        stack.push(id);
+        let value = convert_container(obj, is_array, stack);
+        stack.pop();
+        return value;
+    }
+    Err(PyTypeError::new_err(format!(
+        "object of type {} is not JSON serializable",
+        obj.get_type().name()?
+    )))
+}
+
+/// Convert a list, tuple or dict whose id is on top of `stack`.
+fn convert_container(
+    obj: &Bound<'_, PyAny>,
+    is_array: bool,
+    stack: &mut Vec<usize>,
+) -> PyResult<Value> {
+    if is_array {
+        return obj
+            .try_iter()?
+            .map(|item| convert(&item?, stack))
+            .collect::<PyResult<_>>()
+            .map(Value::Array);
+    }
+    let mut map = Map::new();
+    for (key, item) in obj.downcast::<PyDict>()?.iter() {
+        let Ok(key) = key.downcast::<PyString>() else {
+            return Err(PyTypeError::new_err(format!(
+                "JSON object keys must be str, not {}",
+                key.get_type().name()?
+            )));
+        };
+        map.insert(key.to_cow()?.into_owned(), convert(&item, stack)?);
+    }
+    Ok(Value::Object(map))
+}
+
+/// Serialize a JSON value, compactly or with `indent` spaces per level.
+pub(crate) fn to_vec(value: &Value, indent: Option<usize>) -> serde_json::Result<Vec<u8>> {
+    let Some(width) = indent else {
+        return serde_json::to_vec(value);
+    };
+    let indent = " ".repeat(width);
+    let mut data = Vec::new();
+    let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut data, formatter);
+    value.serialize(&mut serializer)?;
+    Ok(data)
+}
+
+/// Byte offset of a parse error within `data`.
+///
+/// serde_json reports a 1-based line and a byte column within that line.
+pub(crate) fn error_offset(data: &[u8], err: &serde_json::Error) -> usize {
+    let line_start = data
+        .split_inclusive(|&b| b == b'\n')
+        .take(err.line().saturating_sub(1))
+        .map(<[u8]>::len)
+        .sum::<usize>();
+    line_start + err.column().saturating_sub(1)
+}
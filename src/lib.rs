@@ -1,10 +1,68 @@
 #![allow(clippy::useless_conversion)]
 
+mod atomic;
+mod compress;
+mod json;
+mod walk;
+
 use ::path_jail::{Jail as RustJail, JailError};
+use globset::{GlobBuilder, GlobSetBuilder};
+use ignore::gitignore::GitignoreBuilder;
+use ignore::Match;
+use pyo3::create_exception;
 use pyo3::exceptions::{PyIOError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::PyString;
-use std::path::PathBuf;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::mem::ManuallyDrop;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_normalization::UnicodeNormalization;
+
+create_exception!(
+    path_jail,
+    OutsideSubtreeError,
+    PyValueError,
+    "Path is inside the jail but outside the required subdirectory."
+);
+
+create_exception!(
+    path_jail,
+    IntegrityError,
+    PyValueError,
+    "File contents do not match the expected digest."
+);
+
+create_exception!(
+    path_jail,
+    FileTooLargeError,
+    PyValueError,
+    "Existing file exceeds the allowed size."
+);
+
+create_exception!(
+    path_jail,
+    QuotaExceededError,
+    PyValueError,
+    "Write would exceed the allowed number of bytes."
+);
+
+create_exception!(
+    path_jail,
+    StaleFileError,
+    PyValueError,
+    "Existing file is older than the allowed age."
+);
+
+create_exception!(
+    path_jail,
+    ProtectedPathError,
+    PyValueError,
+    "Path is inside a protected subtree of the jail."
+);
 
 /// Maximum path length before we keep the \\?\ prefix on Windows.
 /// Windows MAX_PATH is 260, but we use 250 to leave room for filenames.
@@ -54,6 +112,635 @@ fn path_to_string(path: PathBuf) -> String {
     path.to_string_lossy().into_owned()
 }
 
+/// Normalize a path without touching the filesystem.
+///
+/// Removes `.` components and resolves `..` against preceding components.
+/// `..` never climbs above the start of an absolute path; for relative
+/// paths, leading `..` components that cannot be resolved are kept.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Convert a relative path to a String with `/` separators on every platform.
+/// Only normal components are kept, so the result never contains `.` or `..`.
+fn posix_string(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Convert a filesystem timestamp to seconds since the Unix epoch,
+/// matching the float timestamps in Python's `os.stat_result`.
+fn timestamp(time: SystemTime) -> f64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs_f64(),
+        Err(before) => -before.duration().as_secs_f64(),
+    }
+}
+
+/// Format a digest as lowercase hex.
+fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hash a file's contents in chunks, returning the lowercase hex digest.
+fn file_digest<D: Digest + Write>(path: &Path) -> std::io::Result<String> {
+    let mut hasher = D::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex_digest(&hasher.finalize()))
+}
+
+/// Name a file type the way `Jail.file_type` reports it.
+fn file_type_name(file_type: std::fs::FileType) -> &'static str {
+    if file_type.is_file() {
+        return "file";
+    }
+    if file_type.is_dir() {
+        return "dir";
+    }
+    if file_type.is_symlink() {
+        return "symlink";
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return "fifo";
+        }
+        if file_type.is_socket() {
+            return "socket";
+        }
+        if file_type.is_block_device() {
+            return "block";
+        }
+        if file_type.is_char_device() {
+            return "char";
+        }
+    }
+    "other"
+}
+
+/// Percent-encode a single URL path segment (RFC 3986).
+///
+/// Only unreserved characters are left as is, so `/`, `?`, `#` and `%` in a
+/// file name can never change the structure of the URL.
+fn percent_encode_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Copy the contents of any bytes-like object, as file objects accept.
+fn bytes_like(data: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    if let Ok(bytes) = data.downcast::<PyBytes>() {
+        return Ok(bytes.as_bytes().to_vec());
+    }
+    Ok(data
+        .py()
+        .import("builtins")?
+        .getattr("memoryview")?
+        .call1((data,))?
+        .call_method0("tobytes")?
+        .downcast_into::<PyBytes>()?
+        .as_bytes()
+        .to_vec())
+}
+
+/// Look up the name of a user (or, with `group`, a group) by numeric id.
+///
+/// Returns None if the id has no entry in the passwd (or group) database.
+#[cfg(unix)]
+fn lookup_account_name(id: u32, group: bool) -> std::io::Result<Option<String>> {
+    use std::ffi::CStr;
+
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        // SAFETY: the entry structs are plain C data filled in by libc, and
+        // the name is copied out before `buf` (which it points into) changes
+        let (rc, name) = unsafe {
+            if group {
+                let mut entry: libc::group = std::mem::zeroed();
+                let mut found = std::ptr::null_mut();
+                let rc = libc::getgrgid_r(id, &mut entry, buf.as_mut_ptr(), buf.len(), &mut found);
+                let name = (!found.is_null()).then(|| CStr::from_ptr(entry.gr_name));
+                (rc, name.map(|name| name.to_string_lossy().into_owned()))
+            } else {
+                let mut entry: libc::passwd = std::mem::zeroed();
+                let mut found = std::ptr::null_mut();
+                let rc = libc::getpwuid_r(id, &mut entry, buf.as_mut_ptr(), buf.len(), &mut found);
+                let name = (!found.is_null()).then(|| CStr::from_ptr(entry.pw_name));
+                (rc, name.map(|name| name.to_string_lossy().into_owned()))
+            }
+        };
+        match rc {
+            0 => return Ok(name),
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            _ => return Err(std::io::Error::from_raw_os_error(rc)),
+        }
+    }
+}
+
+/// Like [`lookup_account_name`], but cached for the life of the process and
+/// falling back to the numeric id, so listings look each id up only once.
+#[cfg(unix)]
+fn account_name(id: u32, group: bool) -> std::io::Result<String> {
+    static NAMES: std::sync::Mutex<BTreeMap<(bool, u32), String>> =
+        std::sync::Mutex::new(BTreeMap::new());
+    let cached = NAMES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .get(&(group, id))
+        .cloned();
+    if let Some(name) = cached {
+        return Ok(name);
+    }
+    let name = lookup_account_name(id, group)?.unwrap_or_else(|| id.to_string());
+    NAMES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .insert((group, id), name.clone());
+    Ok(name)
+}
+
+/// Classify a sample from the start of a file as text or binary.
+///
+/// Any NUL byte means binary. Otherwise the sample is text if at most
+/// `max_control_ratio` of its bytes are control characters other than
+/// common whitespace (tab, newline, carriage return, form feed, backspace
+/// and escape), and, with `require_utf8`, it decodes as UTF-8. A multi-byte
+/// sequence cut off at the end of the sample is not held against it.
+fn looks_like_text(sample: &[u8], max_control_ratio: f64, require_utf8: bool) -> bool {
+    if sample.contains(&0) {
+        return false;
+    }
+    if require_utf8 {
+        if let Err(err) = std::str::from_utf8(sample) {
+            if err.error_len().is_some() {
+                return false;
+            }
+        }
+    }
+    let control = sample
+        .iter()
+        .filter(|&&b| (b < 0x20 && !b"\t\n\r\x0c\x08\x1b".contains(&b)) || b == 0x7f)
+        .count();
+    sample.is_empty() || control as f64 <= max_control_ratio * sample.len() as f64
+}
+
+/// Derive a version token from a file's metadata.
+///
+/// Mixes the modification time and size with the inode, device and change
+/// time on Unix, so an atomic replacement or a same-second rewrite still
+/// changes the token.
+fn version_token(metadata: &std::fs::Metadata) -> std::io::Result<u64> {
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(modified.as_nanos().to_be_bytes());
+    hasher.update(metadata.len().to_be_bytes());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        hasher.update(metadata.ino().to_be_bytes());
+        hasher.update(metadata.dev().to_be_bytes());
+        hasher.update(metadata.ctime().to_be_bytes());
+        hasher.update(metadata.ctime_nsec().to_be_bytes());
+    }
+    let mut token = [0u8; 8];
+    token.copy_from_slice(&hasher.finalize()[..8]);
+    Ok(u64::from_be_bytes(token))
+}
+
+/// Take an exclusive advisory lock on the `.<name>.lock` sidecar of `target`.
+///
+/// A sidecar is used because atomic writes replace `target` itself. The lock
+/// is held until the returned file is dropped; the sidecar is left in place.
+fn lock_sidecar(py: Python<'_>, target: &Path) -> PyResult<std::fs::File> {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let lock_path = target.with_file_name(format!(".{}.lock", name));
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path)?;
+    py.allow_threads(|| file.lock())?;
+    Ok(file)
+}
+
+/// Most numbered backups `append_capped` will keep.
+const MAX_BACKUPS: u32 = 1000;
+
+/// Shift `target` into its numbered backups: the oldest (last) backup is
+/// deleted, each remaining one moves up a slot, and `target` becomes the
+/// first. With no backup slots, `target` is simply deleted.
+///
+/// Backups are handled by name and never followed: a symlink in a backup
+/// slot is itself deleted or renamed, leaving whatever it points to alone.
+fn rotate_backups(target: &Path, backups: &[PathBuf]) -> std::io::Result<()> {
+    let occupied = |path: &Path| match path.symlink_metadata() {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    };
+    if let Some(oldest) = backups.last() {
+        if occupied(oldest)? {
+            std::fs::remove_file(oldest)?;
+        }
+    }
+    for pair in backups.windows(2).rev() {
+        if occupied(&pair[0])? {
+            std::fs::rename(&pair[0], &pair[1])?;
+        }
+    }
+    match backups.first() {
+        Some(first) => std::fs::rename(target, first),
+        None => std::fs::remove_file(target),
+    }
+}
+
+/// Open (or create) a counter file for reading and rewriting.
+fn open_counter(path: &Path) -> std::io::Result<std::fs::File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Parse the value of a counter file from its current position. An empty
+/// file counts as 0.
+fn read_counter(file: &mut std::fs::File) -> PyResult<i64> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let contents = contents.trim();
+    if contents.is_empty() {
+        return Ok(0);
+    }
+    contents
+        .parse()
+        .map_err(|_| PyValueError::new_err("counter file does not contain an integer"))
+}
+
+/// Add `amount` to a freshly opened counter file the caller holds an
+/// exclusive lock on, returning the new value.
+fn bump_counter(file: &mut std::fs::File, amount: i64) -> PyResult<i64> {
+    let next = read_counter(file)?
+        .checked_add(amount)
+        .ok_or_else(|| PyValueError::new_err("counter overflow"))?;
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(next.to_string().as_bytes())?;
+    Ok(next)
+}
+
+/// Pick a shard index in `0..shards` from a per-thread xorshift generator,
+/// seeded from the standard library's random hasher keys.
+fn pick_shard(shards: usize) -> usize {
+    use std::hash::{BuildHasher, Hasher};
+
+    thread_local! {
+        static STATE: std::cell::Cell<u64> = std::cell::Cell::new(
+            std::collections::hash_map::RandomState::new().build_hasher().finish() | 1,
+        );
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x % shards as u64) as usize
+    })
+}
+
+/// Chunk size for pulling data from Python file-like objects.
+const STREAM_CHUNK: usize = 64 * 1024;
+
+/// Adapts a Python binary file-like object to `Read`, taking the GIL only
+/// while calling its `read` method. A Python exception is kept in `error`
+/// so it can be re-raised as-is.
+struct PyReader {
+    file: Py<PyAny>,
+    chunk: Vec<u8>,
+    offset: usize,
+    error: Option<PyErr>,
+}
+
+impl PyReader {
+    fn new(file: Py<PyAny>) -> Self {
+        Self {
+            file,
+            chunk: Vec::new(),
+            offset: 0,
+            error: None,
+        }
+    }
+}
+
+impl Read for PyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.offset == self.chunk.len() {
+            let chunk = Python::with_gil(|py| {
+                bytes_like(&self.file.bind(py).call_method1("read", (STREAM_CHUNK,))?)
+            });
+            match chunk {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                }
+                Err(err) => {
+                    self.error = Some(err);
+                    return Err(std::io::Error::other("reading the source failed"));
+                }
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.offset);
+        buf[..n].copy_from_slice(&self.chunk[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// Compare two files' contents, stopping at the first difference.
+fn contents_differ(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (mut a, mut b) = (std::fs::File::open(a)?, std::fs::File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(true);
+    }
+    let (mut buf_a, mut buf_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            // Sizes matched, but the file may have grown since
+            return Ok(b.read(&mut buf_b[..1])? != 0);
+        }
+        match b.read_exact(&mut buf_b[..n]) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(true),
+            Err(err) => return Err(err),
+        }
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(true);
+        }
+    }
+}
+
+/// Compare a file's contents with a buffer, stopping at the first difference.
+fn file_differs_from(path: &Path, data: &[u8]) -> std::io::Result<bool> {
+    let mut file = std::fs::File::open(path)?;
+    if file.metadata()?.len() != data.len() as u64 {
+        return Ok(true);
+    }
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            // Sizes matched, but the file may have shrunk since
+            return Ok(offset != data.len());
+        }
+        if data.get(offset..offset + n) != Some(&buf[..n]) {
+            return Ok(true);
+        }
+        offset += n;
+    }
+}
+
+/// Characters object stores such as S3 recommend avoiding in keys.
+fn is_object_key_unsafe(c: char) -> bool {
+    c.is_ascii_control() || "\\{}^%`[]\"<>~#|".contains(c)
+}
+
+/// Look up the Unicode normalization for a `unicode_form` argument.
+fn unicode_normalizer(form: Option<&str>) -> PyResult<fn(&str) -> String> {
+    Ok(match form {
+        None => |key| key.to_string(),
+        Some("NFC") => |key| key.nfc().collect(),
+        Some("NFD") => |key| key.nfd().collect(),
+        Some("NFKC") => |key| key.nfkc().collect(),
+        Some("NFKD") => |key| key.nfkd().collect(),
+        Some(other) => {
+            return Err(PyValueError::new_err(format!(
+                "unknown unicode_form '{}' (expected NFC, NFD, NFKC or NFKD)",
+                other
+            )))
+        }
+    })
+}
+
+/// Look up the redaction applied by `Jail.redact` for a `redaction` argument.
+fn redactor(policy: &str) -> PyResult<fn(&Path) -> String> {
+    Ok(match policy {
+        "basename" => |rel| {
+            rel.file_name().map_or_else(
+                || ".".to_owned(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        },
+        "hash" => |rel| {
+            let segments: Vec<String> = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let Some((last, parents)) = segments.split_last() else {
+                return ".".to_owned();
+            };
+            parents
+                .iter()
+                .map(|segment| hex_digest(&Sha256::digest(segment.as_bytes())[..4]))
+                .chain([last.clone()])
+                .collect::<Vec<_>>()
+                .join("/")
+        },
+        "relative" => |rel| {
+            let key = posix_string(rel);
+            if key.is_empty() {
+                ".".to_owned()
+            } else {
+                key
+            }
+        },
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "unknown redaction '{}' (expected basename, hash or relative)",
+                other
+            )))
+        }
+    })
+}
+
+/// Check whether any component of a relative path is a dotfile.
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Remove everything inside `dir`, leaving `dir` itself in place.
+///
+/// Symlinks are removed as links and never followed. Each directory is
+/// re-verified to be inside `root` before descending into it, so a
+/// directory swapped for an escaping symlink mid-walk is not emptied.
+fn remove_dir_contents(root: &Path, dir: &Path) -> Result<(), JailError> {
+    let canonical = dir.canonicalize()?;
+    if !canonical.starts_with(root) {
+        return Err(JailError::EscapedRoot {
+            attempted: dir.to_path_buf(),
+            root: root.to_path_buf(),
+        });
+    }
+    for entry in std::fs::read_dir(&canonical)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            remove_dir_contents(root, &entry.path())?;
+            std::fs::remove_dir(entry.path())?;
+        } else if let Err(err) = std::fs::remove_file(entry.path()) {
+            // Directory symlinks and junctions on Windows need remove_dir
+            if !file_type.is_symlink() {
+                return Err(err.into());
+            }
+            std::fs::remove_dir(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// A directory tree built from relative paths; leaves have no children.
+#[derive(Default)]
+struct TreeNode(BTreeMap<String, TreeNode>);
+
+impl TreeNode {
+    fn insert(&mut self, path: &Path) {
+        let mut node = self;
+        for component in path.components() {
+            if let Component::Normal(name) = component {
+                node = node
+                    .0
+                    .entry(name.to_string_lossy().into_owned())
+                    .or_default();
+            }
+        }
+    }
+
+    /// Convert to nested dicts, with None for leaves.
+    fn to_py<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, child) in &self.0 {
+            if child.0.is_empty() {
+                dict.set_item(name, py.None())?;
+            } else {
+                dict.set_item(name, child.to_py(py)?)?;
+            }
+        }
+        Ok(dict)
+    }
+}
+
+/// Borrow the OS file behind a Python file object as a std `File`.
+///
+/// The descriptor stays owned by the Python object, so the returned `File`
+/// is wrapped in `ManuallyDrop` and must not outlive it.
+fn borrow_file(file: &Bound<'_, PyAny>) -> PyResult<ManuallyDrop<std::fs::File>> {
+    let fd: i32 = file.call_method0("fileno")?.extract()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::FromRawFd;
+        // SAFETY: fd is open for as long as the Python file object, and
+        // ManuallyDrop prevents closing it when the borrow ends.
+        Ok(ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::{FromRawHandle, RawHandle};
+        let handle: isize = file
+            .py()
+            .import("msvcrt")?
+            .call_method1("get_osfhandle", (fd,))?
+            .extract()?;
+        // SAFETY: the handle belongs to the open CRT descriptor, and
+        // ManuallyDrop prevents closing it when the borrow ends.
+        Ok(ManuallyDrop::new(unsafe {
+            std::fs::File::from_raw_handle(handle as RawHandle)
+        }))
+    }
+}
+
+/// Chunk size for reading files backwards.
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+/// Find the byte offset where the last `lines` lines of a file begin.
+///
+/// `newline` is the encoded newline, which is also the code unit width:
+/// matches are only accepted at offsets aligned to it, so multi-byte
+/// encodings such as UTF-16 are not split mid-character. A newline at the
+/// very end of the file terminates the last line rather than starting a new
+/// one. Only the first `len` bytes are considered.
+fn tail_offset(
+    file: &mut std::fs::File,
+    len: u64,
+    lines: usize,
+    newline: &[u8],
+) -> std::io::Result<u64> {
+    let width = newline.len() as u64;
+    let align_down = |offset: u64| offset - offset % width;
+    let mut found = 0;
+    let mut end = len;
+    let mut buf = Vec::new();
+    while end > 0 {
+        let start = align_down(end.saturating_sub(TAIL_CHUNK));
+        buf.resize((end - start) as usize, 0);
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+
+        let mut offset = align_down(end.saturating_sub(width));
+        loop {
+            let at = (offset - start) as usize;
+            let is_trailing = offset + width == len;
+            if offset + width <= end && &buf[at..at + width as usize] == newline && !is_trailing {
+                found += 1;
+                if found == lines {
+                    return Ok(offset + width);
+                }
+            }
+            if offset < start + width {
+                break;
+            }
+            offset -= width;
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
 /// Extract a path from a Python object (str or os.PathLike).
 ///
 /// Rejects paths containing null bytes. While Python's open() would also reject them,
@@ -89,6 +776,50 @@ fn extract_path(obj: &Bound<'_, PyAny>) -> PyResult<PathBuf> {
     Err(PyTypeError::new_err("expected str or os.PathLike object"))
 }
 
+/// Shorten a `/`-separated path to at most `max_len` characters by replacing
+/// middle components with `...`, keeping the final component.
+fn truncate_middle(path: &str, max_len: usize) -> String {
+    if path.chars().count() <= max_len {
+        return path.to_string();
+    }
+    let parts: Vec<&str> = path.split('/').collect();
+    let (name, leading) = parts.split_last().expect("split yields at least one part");
+    for keep in (0..leading.len()).rev() {
+        let candidate = if keep == 0 {
+            format!(".../{}", name)
+        } else {
+            format!("{}/.../{}", leading[..keep].join("/"), name)
+        };
+        if candidate.chars().count() <= max_len {
+            return candidate;
+        }
+    }
+    let chars: Vec<char> = name.chars().collect();
+    let budget = max_len.saturating_sub(3).min(chars.len());
+    let (head, tail) = (budget - budget / 2, budget / 2);
+    let mut short: String = chars[..head].iter().collect();
+    short.push_str("...");
+    short.extend(&chars[chars.len() - tail..]);
+    // Below 3 characters even the ellipsis has to be cut short
+    short.chars().take(max_len).collect()
+}
+
+/// Create a symlink at `link` pointing to `target`.
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path, _is_dir: bool) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symlink at `link` pointing to `target`.
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path, is_dir: bool) -> std::io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
 /// Convert JailError to Python exception
 fn to_py_err(err: JailError) -> PyErr {
     match err {
@@ -119,6 +850,68 @@ fn to_py_err(err: JailError) -> PyErr {
 #[pyclass]
 struct Jail {
     inner: RustJail,
+    /// Validated absolute paths of subtrees that must not be modified.
+    protected: Vec<PathBuf>,
+    /// Policy used by `redact`, applied to a validated relative path.
+    redact: fn(&Path) -> String,
+}
+
+impl Jail {
+    /// Extract a Python path argument and validate it against the jail.
+    fn resolve(&self, path: &Bound<'_, PyAny>) -> PyResult<PathBuf> {
+        let path = extract_path(path)?;
+        self.inner.join(&path).map_err(to_py_err)
+    }
+
+    /// Validate a path, returning its location with the final component left
+    /// unresolved, so a symlink there can be inspected instead of followed.
+    ///
+    /// Everything before the final component goes through the jail exactly
+    /// as in `resolve`, so `..` after a symlink climbs from the symlink's
+    /// target, and both name the same entry.
+    fn locate(&self, path: &Bound<'_, PyAny>) -> PyResult<PathBuf> {
+        self.resolve(path)?;
+        self.locate_parent(path)
+    }
+
+    /// Like `locate`, but only the parent is validated: whatever sits at the
+    /// final component (a dangling or escaping symlink included) is left
+    /// alone, for callers that must not follow it at all.
+    fn locate_parent(&self, path: &Bound<'_, PyAny>) -> PyResult<PathBuf> {
+        let raw = extract_path(path)?;
+        let mut components = raw.components();
+        match components.next_back() {
+            Some(Component::Normal(name)) => Ok(self
+                .inner
+                .join(components.as_path())
+                .map_err(to_py_err)?
+                .join(name)),
+            // Ends in `..` or names the root: there is no link to stop at
+            _ => self.resolve(path),
+        }
+    }
+
+    /// Refuse to modify `target` if it is inside a protected subtree, or, for
+    /// operations that affect everything below `target` (removal, moves),
+    /// if a protected subtree is inside it.
+    fn check_unprotected(&self, target: &Path, recursive: bool) -> PyResult<()> {
+        let hit = self.protected.iter().find(|protected| {
+            target.starts_with(protected) || (recursive && protected.starts_with(target))
+        });
+        match hit {
+            Some(protected) => Err(ProtectedPathError::new_err(format!(
+                "path '{}' is protected by '{}'",
+                self.strip_root(target).display(),
+                self.strip_root(protected).display()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Strip the jail root from a path already validated by the jail.
+    fn strip_root<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(self.inner.root()).unwrap_or(path)
+    }
 }
 
 #[pymethods]
@@ -127,14 +920,75 @@ impl Jail {
     ///
     /// Args:
     ///     root: Path to the jail root directory (must exist)
+    ///     protected: Relative paths of subtrees that the jail's mutating
+    ///         helpers must never modify (they need not exist yet)
+    ///     require_writable: If True, create and delete a throwaway file in
+    ///         the root to check that the process can write there
+    ///     redaction: How `redact` shortens paths for client-facing messages:
+    ///         "basename", "hash" or "relative" (default: "basename")
     ///
     /// Raises:
     ///     IOError: If root does not exist or is not a directory
+    ///     PermissionError: If require_writable is set and the root is not
+    ///         writable
+    ///     ValueError: If a protected path would escape the jail, or
+    ///         redaction is not recognized
     #[new]
-    fn new(root: &Bound<'_, PyAny>) -> PyResult<Self> {
+    #[pyo3(signature = (root, protected = None, require_writable = false, redaction = "basename"))]
+    fn new(
+        root: &Bound<'_, PyAny>,
+        protected: Option<&Bound<'_, PyAny>>,
+        require_writable: bool,
+        redaction: &str,
+    ) -> PyResult<Self> {
+        let redact = redactor(redaction)?;
         let path = extract_path(root)?;
         let inner = RustJail::new(&path).map_err(to_py_err)?;
-        Ok(Self { inner })
+        if require_writable {
+            let probe =
+                atomic::create_temp(&inner.root().join("write-probe")).and_then(|(temp, file)| {
+                    drop(file);
+                    std::fs::remove_file(temp)
+                });
+            if let Err(err) = probe {
+                return Err(pyo3::exceptions::PyPermissionError::new_err(format!(
+                    "jail root '{}' is not writable: {}",
+                    path.display(),
+                    err
+                )));
+            }
+        }
+        let mut jail = Self {
+            inner,
+            protected: Vec::new(),
+            redact,
+        };
+        if let Some(protected) = protected {
+            for path in protected.try_iter()? {
+                let resolved = jail.resolve(&path?)?;
+                jail.protected.push(resolved);
+            }
+        }
+        Ok(jail)
+    }
+
+    /// Check whether a path is inside a protected subtree.
+    ///
+    /// Comparison is component-wise on the validated path, so `_system2` is
+    /// not inside `_system`, and a symlink into a protected subtree counts
+    /// as protected.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     True if the jail's mutating helpers would refuse to modify path
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    fn is_protected(&self, path: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        Ok(self.check_unprotected(&resolved, false).is_err())
     }
 
     /// Returns the canonicalized root path.
@@ -143,23 +997,213 @@ impl Jail {
         path_to_string(normalize_path(self.inner.root().to_owned()))
     }
 
+    /// Return the fully symlink-resolved root used for containment checks.
+    ///
+    /// Unlike `root`, this is never shortened for display (on Windows it
+    /// keeps any `\\?\` prefix), so it is exactly the prefix every resolved
+    /// path is compared against. When the root was given through a symlinked
+    /// ancestor (macOS `/tmp` is `/private/tmp`), this is the resolved form.
+    fn canonical_root(&self) -> String {
+        path_to_string(self.inner.root().to_owned())
+    }
+
     /// Safely join a relative path to the jail root.
     ///
     /// Args:
     ///     path: Relative path to join
+    ///     within: Optional relative subdirectory the result must stay under
     ///
     /// Returns:
     ///     Absolute path inside the jail
     ///
     /// Raises:
     ///     ValueError: If path would escape the jail or is absolute
-    fn join(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
-        let path = extract_path(path)?;
-        self.inner
-            .join(&path)
-            .map(normalize_path)
-            .map(path_to_string)
-            .map_err(to_py_err)
+    ///     OutsideSubtreeError: If path is inside the jail but not under `within`
+    #[pyo3(signature = (path, within = None))]
+    fn join(&self, path: &Bound<'_, PyAny>, within: Option<&Bound<'_, PyAny>>) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        if let Some(within) = within {
+            let subtree = self.resolve(within)?;
+            if !resolved.starts_with(&subtree) {
+                return Err(OutsideSubtreeError::new_err(format!(
+                    "path '{}' is not within '{}'",
+                    self.strip_root(&resolved).display(),
+                    self.strip_root(&subtree).display()
+                )));
+            }
+        }
+        Ok(path_to_string(normalize_path(resolved)))
+    }
+
+    /// Join a relative path and enforce a size limit on any existing file.
+    ///
+    /// If the path already exists as a file, its size must not exceed
+    /// `max_bytes`. Paths that do not exist yet are returned as with `join`.
+    ///
+    /// Args:
+    ///     path: Relative path to join
+    ///     max_bytes: Maximum allowed size of an existing file
+    ///
+    /// Returns:
+    ///     Absolute path inside the jail
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or is absolute
+    ///     FileTooLargeError: If the existing file is larger than `max_bytes`
+    fn join_with_limit(&self, path: &Bound<'_, PyAny>, max_bytes: u64) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        if let Ok(metadata) = std::fs::metadata(&resolved) {
+            if metadata.is_file() && metadata.len() > max_bytes {
+                return Err(FileTooLargeError::new_err(format!(
+                    "'{}' is {} bytes, exceeding the limit of {} bytes",
+                    self.strip_root(&resolved).display(),
+                    metadata.len(),
+                    max_bytes
+                )));
+            }
+        }
+        Ok(path_to_string(normalize_path(resolved)))
+    }
+
+    /// Join a relative path to an existing file that must be recent.
+    ///
+    /// The file's age is measured from its creation time where the platform
+    /// and filesystem record one, and from its modification time otherwise.
+    /// Useful for single-use tokens and session files that expire.
+    ///
+    /// Args:
+    ///     path: Relative path to join (must exist)
+    ///     max_age_seconds: Maximum allowed age in seconds
+    ///
+    /// Returns:
+    ///     Absolute path inside the jail
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or is absolute
+    ///     StaleFileError: If the file is older than `max_age_seconds`
+    ///     FileNotFoundError: If the file does not exist
+    fn join_fresh(&self, path: &Bound<'_, PyAny>, max_age_seconds: f64) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        let metadata = std::fs::metadata(&resolved)?;
+        let born = metadata.created().or_else(|_| metadata.modified())?;
+        let age = timestamp(SystemTime::now()) - timestamp(born);
+        if age > max_age_seconds {
+            return Err(StaleFileError::new_err(format!(
+                "'{}' is {:.1} seconds old, exceeding the limit of {} seconds",
+                self.strip_root(&resolved).display(),
+                age,
+                max_age_seconds
+            )));
+        }
+        Ok(path_to_string(normalize_path(resolved)))
+    }
+
+    /// Safely join a path, returning a fallback instead of raising on bad input.
+    ///
+    /// The check is purely lexical and never touches the filesystem: `.` and
+    /// `..` are collapsed as written, and a path that climbs above the root,
+    /// is absolute, or contains a null byte returns `default`. Symlinks are
+    /// not resolved, so a symlink inside the jail can still lead outside
+    /// it; use `join` when the jail may contain untrusted symlinks.
+    ///
+    /// Args:
+    ///     path: Relative path to join
+    ///     default: Value to return if path is rejected
+    ///
+    /// Returns:
+    ///     Absolute path inside the jail, or default
+    ///
+    /// Raises:
+    ///     TypeError: If path is not a str or PathLike
+    fn join_or(&self, path: &Bound<'_, PyAny>, default: Py<PyAny>) -> PyResult<Py<PyAny>> {
+        let py = path.py();
+        let rel = match extract_path(path) {
+            Ok(rel) => rel,
+            // Null bytes; a wrong type is still a TypeError
+            Err(err) if err.is_instance_of::<PyValueError>(py) => return Ok(default),
+            Err(err) => return Err(err),
+        };
+        let lexical = normalize_lexically(&rel);
+        let anchored =
+            rel.has_root() || matches!(rel.components().next(), Some(Component::Prefix(_)));
+        if anchored || lexical.starts_with("..") {
+            return Ok(default);
+        }
+        Ok(path_to_string(self.inner.root().join(lexical))
+            .into_pyobject(py)?
+            .into_any()
+            .unbind())
+    }
+
+    /// Safely join a path, matching each component case-insensitively.
+    ///
+    /// After the lexical containment check, every component is looked up in
+    /// its parent directory: an exact match wins, otherwise the directory is
+    /// scanned for a unique entry equal ignoring case. Each matched prefix is
+    /// re-validated, so a symlink cannot lead the scan outside the jail.
+    /// This is much more expensive than `join`, and every component must
+    /// exist. The GIL is released during the scans.
+    ///
+    /// Args:
+    ///     path: Relative path to join
+    ///
+    /// Returns:
+    ///     Absolute path inside the jail, with the on-disk spelling
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or a component matches
+    ///         more than one entry ignoring case
+    ///     FileNotFoundError: If a component matches no entry
+    fn join_ci(&self, py: Python<'_>, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let root = self.inner.root();
+        let rel = extract_path(path)?;
+        let lexical = normalize_lexically(&root.join(&rel));
+        let Ok(lexical) = lexical.strip_prefix(root) else {
+            return Err(to_py_err(JailError::EscapedRoot {
+                attempted: rel,
+                root: root.to_path_buf(),
+            }));
+        };
+        let resolved = py.allow_threads(|| -> PyResult<PathBuf> {
+            let mut real = PathBuf::new();
+            let mut current = root.to_path_buf();
+            for component in lexical.components() {
+                let wanted = component.as_os_str();
+                let name = if current.join(wanted).symlink_metadata().is_ok() {
+                    wanted.to_owned()
+                } else {
+                    let folded = wanted.to_string_lossy().to_lowercase();
+                    let mut matches = Vec::new();
+                    for entry in std::fs::read_dir(&current)? {
+                        let name = entry?.file_name();
+                        if name.to_string_lossy().to_lowercase() == folded {
+                            matches.push(name);
+                        }
+                    }
+                    match matches.len() {
+                        1 => matches.remove(0),
+                        0 => {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::NotFound,
+                                format!("no entry matching '{}'", real.join(wanted).display()),
+                            )
+                            .into())
+                        }
+                        n => {
+                            return Err(PyValueError::new_err(format!(
+                                "'{}' is ambiguous: {} entries match ignoring case",
+                                real.join(wanted).display(),
+                                n
+                            )))
+                        }
+                    }
+                };
+                real.push(name);
+                current = self.inner.join(&real).map_err(to_py_err)?;
+            }
+            Ok(current)
+        })?;
+        Ok(path_to_string(normalize_path(resolved)))
     }
 
     /// Verify an absolute path is inside the jail.
@@ -200,7 +1244,3021 @@ impl Jail {
             .map_err(to_py_err)
     }
 
-    fn __repr__(&self) -> String {
+    /// Get a view of the jail scoped to one tenant's directory.
+    ///
+    /// Paths passed to the returned TenantJail are joined under
+    /// `tenants/{tenant_id}/`, so handlers cannot forget the prefix. The
+    /// tenant directory does not need to exist yet.
+    ///
+    /// Args:
+    ///     tenant_id: Tenant identifier, used as a single path component
+    ///
+    /// Returns:
+    ///     A TenantJail rooted at the tenant's directory
+    ///
+    /// Raises:
+    ///     ValueError: If tenant_id is empty, "." or "..", or contains a path
+    ///         separator or NUL
+    fn for_tenant(&self, tenant_id: &str) -> PyResult<TenantJail> {
+        if tenant_id.is_empty()
+            || tenant_id == "."
+            || tenant_id == ".."
+            || tenant_id.contains(['/', '\\', '\0'])
+        {
+            return Err(PyValueError::new_err(format!(
+                "invalid tenant id {:?}",
+                tenant_id
+            )));
+        }
+        Ok(TenantJail {
+            inner: self.inner.clone(),
+            tenant_id: tenant_id.to_string(),
+        })
+    }
+
+    /// Get the symlink-free relative path of an existing file inside the jail.
+    ///
+    /// Every component is resolved on disk, so two aliases of the same file
+    /// (e.g. reached through different symlinks) produce the same result.
+    /// Unlike `relative`, the path must exist: no lexical, unresolved tail
+    /// is ever returned, making this suitable as a deduplication key.
+    ///
+    /// Args:
+    ///     path: Relative path, or absolute path inside the jail (must exist)
+    ///
+    /// Returns:
+    ///     Physical relative path from the jail root
+    ///
+    /// Raises:
+    ///     ValueError: If path (or its symlink target) is outside the jail
+    ///     OSError: If the path does not exist
+    fn canonical_relative(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let path = extract_path(path)?;
+        let resolved = if path.is_absolute() {
+            self.inner.contains(&path)
+        } else {
+            self.inner.join(&path)
+        }
+        .map_err(to_py_err)?;
+        // join() leaves non-existent components unresolved; require existence
+        let canonical = resolved.canonicalize()?;
+        Ok(path_to_string(self.strip_root(&canonical).to_path_buf()))
+    }
+
+    /// Validate relative paths and arrange them as a nested directory tree.
+    ///
+    /// Every path is validated against the jail before the tree is built,
+    /// and the tree uses the validated (normalized) relative form. This is
+    /// pure structure: entries that are a prefix of another path become
+    /// nested dicts, and all other entries become leaves.
+    ///
+    /// Args:
+    ///     paths: Iterable of relative paths
+    ///
+    /// Returns:
+    ///     Nested dict mapping names to sub-dicts (directories) or None (leaves)
+    ///
+    /// Raises:
+    ///     ValueError: If any path would escape the jail
+    fn build_tree<'py>(
+        &self,
+        py: Python<'py>,
+        paths: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut tree = TreeNode::default();
+        for path in paths.try_iter()? {
+            let resolved = self.resolve(&path?)?;
+            tree.insert(self.strip_root(&resolved));
+        }
+        tree.to_py(py)
+    }
+
+    /// Validate relative paths and group their leaf names by parent directory.
+    ///
+    /// Grouping uses the validated (normalized) relative form, so `a/./x`
+    /// and `a/y` land in the same bucket. Directories appear in the order
+    /// first seen, and names keep their input order. Files directly in the
+    /// jail root are grouped under ".".
+    ///
+    /// Args:
+    ///     paths: Iterable of relative paths
+    ///
+    /// Returns:
+    ///     Dict mapping each parent directory (relative) to a list of names
+    ///
+    /// Raises:
+    ///     ValueError: If any path would escape the jail or is the jail root
+    fn group_by_dir<'py>(
+        &self,
+        py: Python<'py>,
+        paths: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let groups = PyDict::new(py);
+        for path in paths.try_iter()? {
+            let resolved = self.resolve(&path?)?;
+            let rel = self.strip_root(&resolved);
+            let (Some(name), Some(parent)) = (rel.file_name(), rel.parent()) else {
+                return Err(PyValueError::new_err("cannot group the jail root"));
+            };
+            let parent = if parent.as_os_str().is_empty() {
+                ".".to_owned()
+            } else {
+                path_to_string(parent.to_path_buf())
+            };
+            let name = name.to_string_lossy();
+            match groups.get_item(&parent)? {
+                Some(names) => names.downcast::<PyList>()?.append(name)?,
+                None => groups.set_item(parent, PyList::new(py, [name])?)?,
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Map a batch of paths to canonical keys, revealing aliases.
+    ///
+    /// Each path is validated and resolved (symlinks followed) in one pass
+    /// with the GIL released, then reduced to its `/`-separated relative
+    /// key. Inputs that reach the same file through different spellings or
+    /// links get the same key.
+    ///
+    /// Args:
+    ///     paths: Iterable of relative paths
+    ///     case_insensitive: Lowercase keys, for case-insensitive storage
+    ///         (default: False)
+    ///     unicode_form: Normalize keys to "NFC", "NFD", "NFKC" or "NFKD"
+    ///         (default: None, no normalization)
+    ///     strict: Raise on an escaping path instead of mapping it to None
+    ///         (default: True)
+    ///
+    /// Returns:
+    ///     Dict mapping each input to its canonical key (or None)
+    ///
+    /// Raises:
+    ///     ValueError: If a path would escape the jail (when strict), or
+    ///         unicode_form is not recognized
+    #[pyo3(signature = (paths, *, case_insensitive = false, unicode_form = None, strict = true))]
+    fn dedup_map<'py>(
+        &self,
+        py: Python<'py>,
+        paths: &Bound<'py, PyAny>,
+        case_insensitive: bool,
+        unicode_form: Option<&str>,
+        strict: bool,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let normalize = unicode_normalizer(unicode_form)?;
+        let mut inputs = Vec::new();
+        let mut relative = Vec::new();
+        for path in paths.try_iter()? {
+            let path = path?;
+            relative.push(extract_path(&path)?);
+            inputs.push(path);
+        }
+        let keys = py.allow_threads(|| {
+            relative
+                .iter()
+                .map(|path| {
+                    let resolved = self.inner.join(path)?;
+                    let key = normalize(&posix_string(self.strip_root(&resolved)));
+                    Ok(if case_insensitive {
+                        key.to_lowercase()
+                    } else {
+                        key
+                    })
+                })
+                .collect::<Vec<Result<String, JailError>>>()
+        });
+        let map = PyDict::new(py);
+        for (input, key) in inputs.iter().zip(keys) {
+            match key {
+                Ok(key) => map.set_item(input, key)?,
+                Err(JailError::Io(err)) => return Err(err.into()),
+                Err(err) if strict => return Err(to_py_err(err)),
+                Err(_) => map.set_item(input, py.None())?,
+            }
+        }
+        Ok(map)
+    }
+
+    /// List the ancestors of a path inside the jail with existence flags.
+    ///
+    /// Covers each directory level from just below the jail root down to the
+    /// path's parent, so a provisioning step can create exactly the missing
+    /// levels. Stats stop at the first missing level, since everything below
+    /// it is missing too.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     List of (relpath, exists) tuples, shallowest first, where
+    ///         exists is True only for an existing directory
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    fn ancestors_status(&self, path: &Bound<'_, PyAny>) -> PyResult<Vec<(String, bool)>> {
+        let resolved = self.resolve(path)?;
+        let rel = self.strip_root(&resolved);
+        let mut levels: Vec<&Path> = rel.ancestors().skip(1).collect();
+        // ancestors() ends with the empty path, which is the root itself
+        levels.pop();
+        levels.reverse();
+        let mut exists = true;
+        Ok(levels
+            .into_iter()
+            .map(|level| {
+                exists = exists && self.inner.root().join(level).is_dir();
+                (path_to_string(level.to_path_buf()), exists)
+            })
+            .collect())
+    }
+
+    /// Split a path inside the jail into its existing and missing parts.
+    ///
+    /// The first part is the deepest ancestor (or the path itself) that
+    /// exists; the second is the components below it that do not, ready for
+    /// a single `os.makedirs(os.path.join(jail.join(prefix), suffix))`.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     Tuple of (existing_prefix, missing_suffix), where the prefix is "."
+    ///         for the jail root and the suffix is "" if path exists
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    fn split_existing(&self, path: &Bound<'_, PyAny>) -> PyResult<(String, String)> {
+        let resolved = self.resolve(path)?;
+        let rel = self.strip_root(&resolved);
+        let mut existing = PathBuf::new();
+        let mut components = rel.components();
+        while let Some(component) = components.clone().next() {
+            if !self.inner.root().join(&existing).join(component).exists() {
+                break;
+            }
+            existing.push(component);
+            components.next();
+        }
+        let prefix = if existing.as_os_str().is_empty() {
+            ".".to_owned()
+        } else {
+            path_to_string(existing)
+        };
+        Ok((prefix, path_to_string(components.as_path().to_path_buf())))
+    }
+
+    /// Compute a stable 64-bit identifier for a path inside the jail.
+    ///
+    /// The path is validated and resolved like `dedup_map`, and the id is
+    /// the first 8 bytes (big-endian) of the SHA-256 digest of its UTF-8
+    /// `/`-separated relative key. It is the same across processes and
+    /// platforms for the same key. It is not a cryptographic commitment:
+    /// 64 bits allow deliberate collisions, so do not use it as an access
+    /// control token.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///     case_insensitive: Lowercase the key first (default: False)
+    ///     unicode_form: Normalize the key to "NFC", "NFD", "NFKC" or "NFKD"
+    ///         first (default: None, no normalization)
+    ///
+    /// Returns:
+    ///     Unsigned 64-bit integer
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or unicode_form is not
+    ///         recognized
+    #[pyo3(signature = (path, *, case_insensitive = false, unicode_form = None))]
+    fn path_id(
+        &self,
+        path: &Bound<'_, PyAny>,
+        case_insensitive: bool,
+        unicode_form: Option<&str>,
+    ) -> PyResult<u64> {
+        let normalize = unicode_normalizer(unicode_form)?;
+        let resolved = self.resolve(path)?;
+        let mut key = normalize(&posix_string(self.strip_root(&resolved)));
+        if case_insensitive {
+            key = key.to_lowercase();
+        }
+        let digest = Sha256::digest(key.as_bytes());
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&digest[..8]);
+        Ok(u64::from_be_bytes(id))
+    }
+
+    /// Get the relative path of an absolute path, optionally without
+    /// touching the filesystem.
+    ///
+    /// With `resolve=False`, the input is normalized lexically (`.` and `..`
+    /// are collapsed, symlinks are not resolved) and the jail root is
+    /// stripped from it. The path does not need to exist. The input must use
+    /// the same form as `root` (e.g. `/private/var` rather than `/var` on
+    /// macOS). With `resolve=True`, this behaves like `relative`.
+    ///
+    /// Args:
+    ///     abs_path: Absolute path believed to be inside the jail
+    ///     resolve: Resolve symlinks on disk (path must exist)
+    ///
+    /// Returns:
+    ///     Relative path from the jail root
+    ///
+    /// Raises:
+    ///     ValueError: If the path is not absolute or is outside the jail
+    #[pyo3(signature = (abs_path, resolve = false))]
+    fn relative_of_absolute(&self, abs_path: &Bound<'_, PyAny>, resolve: bool) -> PyResult<String> {
+        let path = extract_path(abs_path)?;
+        if !path.is_absolute() {
+            return Err(PyValueError::new_err("invalid path: path must be absolute"));
+        }
+        if resolve {
+            return self
+                .inner
+                .relative(&path)
+                .map(path_to_string)
+                .map_err(to_py_err);
+        }
+        let normalized = normalize_lexically(&path);
+        let root = self.inner.root();
+        let display_root = normalize_path(root.to_path_buf());
+        normalized
+            .strip_prefix(root)
+            .or_else(|_| normalized.strip_prefix(&display_root))
+            .map(|rel| path_to_string(rel.to_path_buf()))
+            .map_err(|_| {
+                to_py_err(JailError::EscapedRoot {
+                    attempted: path.clone(),
+                    root: root.to_path_buf(),
+                })
+            })
+    }
+
+    /// Compare the lexical and physical resolution of a path.
+    ///
+    /// The lexical form collapses `.` and `..` without touching the
+    /// filesystem; the physical form resolves symlinks like `join`. If the
+    /// two differ, symlinks redirected the path.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     Tuple of (lexical, physical) paths relative to the jail root
+    ///
+    /// Raises:
+    ///     ValueError: If either interpretation escapes the jail
+    fn resolution_delta(&self, path: &Bound<'_, PyAny>) -> PyResult<(String, String)> {
+        let root = self.inner.root();
+        let lexical = normalize_lexically(&root.join(extract_path(path)?));
+        let Ok(lexical) = lexical.strip_prefix(root) else {
+            return Err(to_py_err(JailError::EscapedRoot {
+                attempted: lexical,
+                root: root.to_path_buf(),
+            }));
+        };
+        let physical = self.resolve(path)?;
+        Ok((
+            path_to_string(lexical.to_path_buf()),
+            path_to_string(self.strip_root(&physical).to_path_buf()),
+        ))
+    }
+
+    /// Check whether writing to a path would modify a file outside the jail.
+    ///
+    /// The path must stay inside the jail lexically; this then reports
+    /// whether a symlink on the way (including the final component) leads
+    /// outside, so a naive `open(jail.root + path, "w")` would write through
+    /// it. A dangling symlink counts when its target lies outside the jail.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     True if a write would land outside the jail
+    ///
+    /// Raises:
+    ///     ValueError: If path escapes the jail lexically or is invalid
+    fn write_would_escape(&self, path: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let root = self.inner.root();
+        let rel = extract_path(path)?;
+        let lexical = normalize_lexically(&root.join(&rel));
+        if !lexical.starts_with(root) {
+            return Err(to_py_err(JailError::EscapedRoot {
+                attempted: rel,
+                root: root.to_path_buf(),
+            }));
+        }
+        match self.inner.join(&rel) {
+            Ok(_) => Ok(false),
+            Err(JailError::EscapedRoot { .. }) => Ok(true),
+            Err(JailError::BrokenSymlink(link)) => {
+                let target = std::fs::read_link(&link)?;
+                let mut target = normalize_lexically(&link.parent().unwrap_or(root).join(target));
+                // Resolve the deepest existing ancestor so symlinked parents
+                // of the dangling target are accounted for.
+                let mut rest = Vec::new();
+                while !target.exists() {
+                    match (target.file_name(), target.parent()) {
+                        (Some(name), Some(parent)) => {
+                            rest.push(name.to_owned());
+                            target = parent.to_path_buf();
+                        }
+                        _ => break,
+                    }
+                }
+                let mut resolved = target.canonicalize().unwrap_or(target);
+                resolved.extend(rest.iter().rev());
+                Ok(!resolved.starts_with(root))
+            }
+            Err(err) => Err(to_py_err(err)),
+        }
+    }
+
+    /// List entries of a directory inside the jail with given extensions.
+    ///
+    /// Extensions are compared case-insensitively against the end of each
+    /// name and may be given with or without the leading ".", so multi-part
+    /// suffixes such as ".tar.gz" work. Filtering happens during the
+    /// directory scan, with the GIL released. Only regular files are listed:
+    /// symlinks count if they lead to a file inside the jail, while links to
+    /// directories, dangling links and links escaping the jail are skipped.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory (default: jail root)
+    ///     extensions: Extensions to keep, e.g. {".jpg", ".png"}
+    ///
+    /// Returns:
+    ///     Sorted list of matching entry names
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path = None, *, extensions))]
+    fn listdir_ext(
+        &self,
+        py: Python<'_>,
+        path: Option<&Bound<'_, PyAny>>,
+        extensions: HashSet<String>,
+    ) -> PyResult<Vec<String>> {
+        let dir = match path {
+            Some(path) => self.resolve(path)?,
+            None => self.inner.root().to_path_buf(),
+        };
+        let suffixes: Vec<String> = extensions
+            .iter()
+            .map(|ext| format!(".{}", ext.trim_start_matches('.').to_lowercase()))
+            .collect();
+        let root = self.inner.root();
+        let names = py.allow_threads(|| -> std::io::Result<Vec<String>> {
+            let mut names = Vec::new();
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let lower = name.to_lowercase();
+                // The suffix must follow a non-empty stem, as with extension()
+                let matches = suffixes
+                    .iter()
+                    .any(|suffix| lower.len() > suffix.len() && lower.ends_with(suffix.as_str()));
+                if !matches {
+                    continue;
+                }
+                let path = entry.path();
+                if entry.file_type()?.is_symlink() {
+                    match path.canonicalize() {
+                        Ok(target) if target.starts_with(root) && target.is_file() => {}
+                        _ => continue,
+                    }
+                } else if !entry.file_type()?.is_file() {
+                    continue;
+                }
+                names.push(name);
+            }
+            names.sort();
+            Ok(names)
+        })?;
+        Ok(names)
+    }
+
+    /// Format a path inside the jail for display, truncating long paths.
+    ///
+    /// Returns the jail-relative path with `/` separators. If it is longer
+    /// than `max_len` characters, middle components are replaced with `...`,
+    /// keeping the file name and as many leading components as fit
+    /// (`users/.../file.txt`). A file name that does not fit on its own is
+    /// itself truncated in the middle; with a `max_len` under 4 only (part
+    /// of) the `...` is left.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///     max_len: Maximum length of the result in characters (default: 60)
+    ///
+    /// Returns:
+    ///     Display string of at most max_len characters
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    #[pyo3(signature = (path, max_len = 60))]
+    fn display(&self, path: &Bound<'_, PyAny>, max_len: usize) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        Ok(truncate_middle(
+            &posix_string(self.strip_root(&resolved)),
+            max_len,
+        ))
+    }
+
+    /// Format a path inside the jail for client-facing error messages.
+    ///
+    /// The path is validated, then shortened by the jail's `redaction`
+    /// policy so the message names the file without exposing the layout:
+    /// "basename" keeps only the final component, "hash" replaces each
+    /// parent directory with 8 hex digits of its SHA-256, and "relative"
+    /// keeps the full jail-relative path. The server root is never included.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     Redacted path with `/` separators ("." for the jail root)
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    fn redact(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        Ok((self.redact)(self.strip_root(&resolved)))
+    }
+
+    /// Split a path inside the jail into percent-encoded URL segments.
+    ///
+    /// Each component of the jail-relative path is encoded separately, so a
+    /// router can assemble resource URLs without re-encoding. Characters
+    /// other than letters, digits and `-._~` are percent-encoded as UTF-8.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     List of encoded segments (empty for the jail root)
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    fn url_segments(&self, path: &Bound<'_, PyAny>) -> PyResult<Vec<String>> {
+        let resolved = self.resolve(path)?;
+        Ok(self
+            .strip_root(&resolved)
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(percent_encode_segment(&name.to_string_lossy())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Compute an object-store key (e.g. for S3) for a path inside the jail.
+    ///
+    /// The key is the jail-relative path with `/` separators, after
+    /// `prefix`. It never starts with `/` and never contains empty, `.` or
+    /// `..` segments, so it cannot encode a traversal in the mirror.
+    /// Characters that object stores recommend avoiding (control characters,
+    /// backslash, and any of {}^%`[]"<>~#|) are rejected, or percent-encoded
+    /// with `encode=True`.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///     prefix: Key prefix, e.g. "backups/host1" (default: "")
+    ///     encode: Percent-encode problematic characters instead of
+    ///         rejecting them (default: False)
+    ///
+    /// Returns:
+    ///     The object key
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or refers to the jail
+    ///         root, prefix has empty, "." or ".." segments, or the key
+    ///         contains a problematic character and encode is False
+    #[pyo3(signature = (path, prefix = "", encode = false))]
+    fn object_key(&self, path: &Bound<'_, PyAny>, prefix: &str, encode: bool) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        let rel = posix_string(self.strip_root(&resolved));
+        if rel.is_empty() {
+            return Err(PyValueError::new_err("jail root has no object key"));
+        }
+        let prefix = prefix.trim_matches('/');
+        if !prefix.is_empty()
+            && prefix
+                .split('/')
+                .any(|segment| matches!(segment, "" | "." | ".."))
+        {
+            return Err(PyValueError::new_err(format!(
+                "invalid key prefix '{}': empty, '.' or '..' segment",
+                prefix
+            )));
+        }
+        let key = if prefix.is_empty() {
+            rel
+        } else {
+            format!("{}/{}", prefix, rel)
+        };
+        if !encode {
+            if let Some(c) = key.chars().find(|&c| is_object_key_unsafe(c)) {
+                return Err(PyValueError::new_err(format!(
+                    "object key '{}' contains problematic character {:?}",
+                    key, c
+                )));
+            }
+            return Ok(key);
+        }
+        let mut encoded = String::with_capacity(key.len());
+        for c in key.chars() {
+            if is_object_key_unsafe(c) {
+                encoded.push_str(&format!("%{:02X}", c as u32));
+            } else {
+                encoded.push(c);
+            }
+        }
+        Ok(encoded)
+    }
+
+    /// Build the command that reveals a path in the platform's file manager.
+    ///
+    /// Returns an argument list for `subprocess.run`: `explorer /select,`
+    /// on Windows and `open -R` on macOS, which both highlight the entry.
+    /// Elsewhere, `xdg-open` on the containing directory is returned, since
+    /// freedesktop file managers have no common "select" flag. The path is
+    /// validated and canonicalized first, so the command can never point
+    /// outside the jail.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail (must exist)
+    ///
+    /// Returns:
+    ///     Command and arguments as a list of strings
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     FileNotFoundError: If the path does not exist
+    fn reveal_target(&self, path: &Bound<'_, PyAny>) -> PyResult<Vec<String>> {
+        let resolved = self.resolve(path)?;
+        std::fs::symlink_metadata(&resolved)?;
+        let target = normalize_path(resolved);
+        #[cfg(windows)]
+        let command = vec![
+            "explorer".to_string(),
+            format!("/select,{}", target.display()),
+        ];
+        #[cfg(target_os = "macos")]
+        let command = vec!["open".to_string(), "-R".to_string(), path_to_string(target)];
+        #[cfg(not(any(windows, target_os = "macos")))]
+        let command = {
+            let dir = match target.parent() {
+                Some(parent) if target != self.inner.root() => parent.to_path_buf(),
+                _ => target,
+            };
+            vec!["xdg-open".to_string(), path_to_string(dir)]
+        };
+        Ok(command)
+    }
+
+    /// Compute a safe archive member name for a path inside the jail.
+    ///
+    /// Returns the jail-relative path with `/` separators, suitable as a
+    /// tar or zip entry name. The result is never absolute and never
+    /// contains `..`, so archives built from it cannot be zip-slip vectors.
+    /// A symlink is named by its own path, not its target's, so it can be
+    /// archived as a link.
+    ///
+    /// Args:
+    ///     path: Relative path, or absolute path inside the jail (must exist)
+    ///
+    /// Returns:
+    ///     POSIX-separated relative name
+    ///
+    /// Raises:
+    ///     ValueError: If path is outside the jail or refers to the jail root
+    fn archive_name(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let raw = extract_path(path)?;
+        // A symlink member keeps its own name: only its parent is resolved
+        let rel = if raw.is_absolute() {
+            // Validates the whole path, which must exist
+            let rel = self.inner.relative(&raw).map_err(to_py_err)?;
+            match (raw.parent(), raw.file_name()) {
+                (Some(parent), Some(name)) if !rel.as_os_str().is_empty() => {
+                    self.inner.relative(parent).map_err(to_py_err)?.join(name)
+                }
+                _ => rel,
+            }
+        } else {
+            self.strip_root(&self.locate(path)?).to_path_buf()
+        };
+        let name = posix_string(&rel);
+        if name.is_empty() {
+            return Err(PyValueError::new_err(
+                "jail root has no archive member name",
+            ));
+        }
+        Ok(name)
+    }
+
+    /// Return the number of hard links to a file inside the jail.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     The file's link count (`st_nlink`)
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    ///     NotImplementedError: On platforms without link counts (Windows)
+    fn link_count(&self, path: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let resolved = self.resolve(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Ok(std::fs::metadata(&resolved)?.nlink())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = resolved;
+            Err(pyo3::exceptions::PyNotImplementedError::new_err(
+                "link_count is only supported on Unix",
+            ))
+        }
+    }
+
+    /// Return the disk space actually allocated to a file inside the jail.
+    ///
+    /// This is `st_blocks * 512`, which for sparse files (VM images,
+    /// databases) can be far smaller than the logical size reported by
+    /// `os.path.getsize`, and for small files is usually larger.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     Allocated size in bytes
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    ///     NotImplementedError: On platforms without block counts (Windows)
+    fn allocated_size(&self, path: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let resolved = self.resolve(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Ok(std::fs::metadata(&resolved)?.blocks() * 512)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = resolved;
+            Err(pyo3::exceptions::PyNotImplementedError::new_err(
+                "allocated_size is only supported on Unix",
+            ))
+        }
+    }
+
+    /// Return the name of the user that owns a file inside the jail.
+    ///
+    /// The uid is resolved through the passwd database (`getpwuid_r`), and
+    /// lookups are cached for the life of the process, so listing a large
+    /// directory stays cheap. Symlinks are followed.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     User name, or the numeric uid as a string if it has no entry
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    ///     NotImplementedError: On platforms without Unix owners (Windows)
+    fn owner(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Ok(account_name(std::fs::metadata(&resolved)?.uid(), false)?)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = resolved;
+            Err(pyo3::exceptions::PyNotImplementedError::new_err(
+                "owner is only supported on Unix",
+            ))
+        }
+    }
+
+    /// Return the name of the group that owns a file inside the jail.
+    ///
+    /// The gid is resolved through the group database (`getgrgid_r`), with
+    /// the same process-wide cache as `owner`. Symlinks are followed.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     Group name, or the numeric gid as a string if it has no entry
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    ///     NotImplementedError: On platforms without Unix groups (Windows)
+    fn group(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Ok(account_name(std::fs::metadata(&resolved)?.gid(), true)?)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = resolved;
+            Err(pyo3::exceptions::PyNotImplementedError::new_err(
+                "group is only supported on Unix",
+            ))
+        }
+    }
+
+    /// Count the newline bytes in a file inside the jail.
+    ///
+    /// Scans the file in chunks in Rust with the GIL released, without
+    /// decoding it. This counts `\n` occurrences, so a last line without a
+    /// trailing newline is not counted; add one if the file is non-empty and
+    /// does not end in `\n` when an exact line count matters.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     Number of `\n` bytes in the file
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be read
+    fn line_count(&self, py: Python<'_>, path: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let resolved = self.resolve(path)?;
+        let count = py.allow_threads(|| -> std::io::Result<u64> {
+            let mut file = std::fs::File::open(&resolved)?;
+            let mut buf = vec![0; STREAM_CHUNK];
+            let mut count = 0;
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => return Ok(count),
+                    Ok(n) => count += buf[..n].iter().filter(|&&b| b == b'\n').count() as u64,
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        })?;
+        Ok(count)
+    }
+
+    /// Guess whether a file inside the jail is text or binary.
+    ///
+    /// Reads up to `sample` bytes from the start of the file and classifies
+    /// them the way editors and diff tools do: any NUL byte means binary;
+    /// otherwise the file is text if at most `max_control_ratio` of the
+    /// sampled bytes are control characters other than common whitespace,
+    /// and (with `require_utf8`) the sample decodes as UTF-8. Empty files
+    /// count as text.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///     sample: Number of bytes to inspect (default: 8192)
+    ///     max_control_ratio: Largest fraction of control bytes a text file
+    ///         may contain, from 0.0 to 1.0 (default: 0.3)
+    ///     require_utf8: Also require the sample to be valid UTF-8, which
+    ///         rejects Latin-1 and similar legacy encodings (default: False)
+    ///
+    /// Returns:
+    ///     True if the file looks like text
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, sample is 0, or
+    ///         max_control_ratio is outside 0.0 to 1.0
+    ///     OSError: If the file cannot be read
+    #[pyo3(signature = (path, sample = 8192, max_control_ratio = 0.3, require_utf8 = false))]
+    fn is_text(
+        &self,
+        path: &Bound<'_, PyAny>,
+        sample: u64,
+        max_control_ratio: f64,
+        require_utf8: bool,
+    ) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        if sample == 0 {
+            return Err(PyValueError::new_err("sample must be positive"));
+        }
+        if !(0.0..=1.0).contains(&max_control_ratio) {
+            return Err(PyValueError::new_err(
+                "max_control_ratio must be between 0.0 and 1.0",
+            ));
+        }
+        let mut head = Vec::new();
+        std::fs::File::open(&resolved)?
+            .take(sample)
+            .read_to_end(&mut head)?;
+        Ok(looks_like_text(&head, max_control_ratio, require_utf8))
+    }
+
+    /// Decide whether a path is safe to serve as a static file.
+    ///
+    /// Bundles the checks a file server needs before sending bytes: the path
+    /// validates against the jail, exists, is a regular file, is not hidden
+    /// (no component starting with "."), and matches one of the allow-globs.
+    /// Escaping or malformed paths (including null bytes) produce a verdict
+    /// rather than raising.
+    ///
+    /// Args:
+    ///     path: Relative path requested by the client
+    ///     allow_hidden: Allow dotfiles and dot-directories (default: False)
+    ///     allow: Glob patterns matched against the `/`-separated relative
+    ///         path; if given, the path must match at least one. `*` stays
+    ///         within one directory; use `**` to match across directories
+    ///
+    /// Returns:
+    ///     Tuple of (servable, reason). reason is None when servable, otherwise
+    ///     one of "invalid", "not_found", "not_file", "hidden", "not_allowed"
+    ///
+    /// Raises:
+    ///     TypeError: If path is not a str or PathLike
+    ///     ValueError: If an allow pattern is not a valid glob
+    #[pyo3(signature = (path, allow_hidden = false, allow = None))]
+    fn is_servable(
+        &self,
+        path: &Bound<'_, PyAny>,
+        allow_hidden: bool,
+        allow: Option<Vec<String>>,
+    ) -> PyResult<(bool, Option<&'static str>)> {
+        let requested = match extract_path(path) {
+            Ok(requested) => requested,
+            Err(err) if err.is_instance_of::<PyValueError>(path.py()) => {
+                return Ok((false, Some("invalid")))
+            }
+            Err(err) => return Err(err),
+        };
+        let resolved = match self.inner.join(&requested) {
+            Ok(resolved) => resolved,
+            Err(JailError::Io(err)) => return Err(err.into()),
+            Err(_) => return Ok((false, Some("invalid"))),
+        };
+        let metadata = match std::fs::metadata(&resolved) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok((false, Some("not_found")))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if !metadata.is_file() {
+            return Ok((false, Some("not_file")));
+        }
+        let rel = self.strip_root(&resolved);
+        if !allow_hidden && (is_hidden(&requested) || is_hidden(rel)) {
+            return Ok((false, Some("hidden")));
+        }
+        if let Some(patterns) = allow {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in &patterns {
+                let glob = GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| {
+                        PyValueError::new_err(format!("invalid glob '{}': {}", pattern, e))
+                    })?;
+                builder.add(glob);
+            }
+            let globs = builder
+                .build()
+                .map_err(|e| PyValueError::new_err(format!("invalid glob set: {}", e)))?;
+            if !globs.is_match(posix_string(rel)) {
+                return Ok((false, Some("not_allowed")));
+            }
+        }
+        Ok((true, None))
+    }
+
+    /// Set or clear the read-only flag on a file inside the jail.
+    ///
+    /// On Unix, setting clears all write permission bits and clearing
+    /// restores the owner write bit only. On Windows, this toggles the
+    /// read-only file attribute.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///     readonly: True to make read-only, False to make writable
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If permissions cannot be changed
+    #[pyo3(signature = (path, readonly = true))]
+    fn set_readonly(&self, path: &Bound<'_, PyAny>, readonly: bool) -> PyResult<()> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let mut permissions = std::fs::metadata(&resolved)?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = if readonly {
+                permissions.mode() & !0o222
+            } else {
+                permissions.mode() | 0o200
+            };
+            permissions.set_mode(mode);
+        }
+        #[cfg(not(unix))]
+        permissions.set_readonly(readonly);
+        std::fs::set_permissions(&resolved, permissions)?;
+        Ok(())
+    }
+
+    /// Check whether a file inside the jail is read-only.
+    ///
+    /// On Unix, a file is read-only when it has no write permission bits.
+    /// On Windows, this reflects the read-only file attribute.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     True if the file is read-only
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    fn is_readonly(&self, path: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        Ok(std::fs::metadata(&resolved)?.permissions().readonly())
+    }
+
+    /// Classify an entry inside the jail with a single stat call.
+    ///
+    /// Args:
+    ///     path: Relative path to the entry (must exist)
+    ///     follow_symlinks: Report the type of a symlink's target rather than
+    ///         "symlink" (default: False)
+    ///
+    /// Returns:
+    ///     One of "file", "dir", "symlink", "fifo", "socket", "block",
+    ///     "char" or "other"
+    ///
+    /// Raises:
+    ///     ValueError: If path (or its symlink target) would escape the jail
+    ///     FileNotFoundError: If the path does not exist
+    #[pyo3(signature = (path, follow_symlinks = false))]
+    fn file_type(&self, path: &Bound<'_, PyAny>, follow_symlinks: bool) -> PyResult<&'static str> {
+        let metadata = if follow_symlinks {
+            std::fs::metadata(self.resolve(path)?)?
+        } else {
+            std::fs::symlink_metadata(self.locate(path)?)?
+        };
+        Ok(file_type_name(metadata.file_type()))
+    }
+
+    /// Return the creation (birth) time of a file inside the jail.
+    ///
+    /// Uses `st_birthtime` on macOS/BSD, the creation time on Windows, and
+    /// `statx` on Linux. Unlike `st_ctime`, which is the inode change time
+    /// on Unix, this is when the file was created.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     Seconds since the Unix epoch, or None if the platform or
+    ///     filesystem does not record creation times
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    fn created_time(&self, path: &Bound<'_, PyAny>) -> PyResult<Option<f64>> {
+        let resolved = self.resolve(path)?;
+        match std::fs::metadata(&resolved)?.created() {
+            Ok(created) => Ok(Some(timestamp(created))),
+            Err(err) if err.kind() == std::io::ErrorKind::Unsupported => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Remove all contents of a directory inside the jail, keeping the directory.
+    ///
+    /// Unlike `shutil.rmtree`, the directory itself is left in place (useful
+    /// for mount points and cache directories). Symlinks are removed without
+    /// being followed, and every directory is re-verified to be inside the
+    /// jail before descending. The GIL is released during removal.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory
+    ///
+    /// Raises:
+    ///     ValueError: If path (or a directory below it) would escape the jail
+    ///     ProtectedPathError: If path is inside, or contains, a protected subtree
+    ///     OSError: If path is not a directory or an entry cannot be removed
+    fn empty_dir(&self, py: Python<'_>, path: &Bound<'_, PyAny>) -> PyResult<()> {
+        let dir = self.resolve(path)?;
+        self.check_unprotected(&dir, true)?;
+        let root = self.inner.root();
+        py.allow_threads(|| remove_dir_contents(root, &dir))
+            .map_err(to_py_err)
+    }
+
+    /// Atomically publish a directory's new contents from a staging directory.
+    ///
+    /// `source` is built by the caller and then swapped with `target` in one
+    /// rename (`renameat2(RENAME_EXCHANGE)` on Linux, `renamex_np` on macOS),
+    /// so readers of `target` see either the complete old tree or the
+    /// complete new one. The old contents, left at `source` by the swap, are
+    /// then removed. Where the filesystem has no exchange primitive, `target`
+    /// is renamed aside before `source` is renamed into place, leaving a
+    /// brief window in which `target` does not exist (but is never partial).
+    /// If `target` does not exist yet, `source` is simply renamed to it. Both
+    /// must be on the same filesystem. The GIL is released throughout.
+    ///
+    /// Args:
+    ///     target: Relative path to the directory to replace
+    ///     source: Relative path to the staging directory with the new contents
+    ///
+    /// Raises:
+    ///     ValueError: If either path would escape the jail, is the jail root,
+    ///         or one is inside the other
+    ///     ProtectedPathError: If either path is inside, or contains, a protected
+    ///         subtree
+    ///     OSError: If source or target is not a directory, or the swap fails
+    fn replace_dir_contents(
+        &self,
+        py: Python<'_>,
+        target: &Bound<'_, PyAny>,
+        source: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        let target = self.resolve(target)?;
+        let source = self.resolve(source)?;
+        let root = self.inner.root();
+        if target == root || source == root {
+            return Err(PyValueError::new_err("cannot replace the jail root"));
+        }
+        if target.starts_with(&source) || source.starts_with(&target) {
+            return Err(PyValueError::new_err(format!(
+                "'{}' and '{}' must not contain each other",
+                self.strip_root(&target).display(),
+                self.strip_root(&source).display()
+            )));
+        }
+        self.check_unprotected(&target, true)?;
+        self.check_unprotected(&source, true)?;
+        py.allow_threads(|| -> Result<(), JailError> {
+            let require_dir = |dir: &Path| -> std::io::Result<()> {
+                if std::fs::symlink_metadata(dir)?.is_dir() {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::NotADirectory,
+                        format!("'{}' is not a directory", dir.display()),
+                    ))
+                }
+            };
+            require_dir(&source)?;
+            if !target.exists() {
+                std::fs::rename(&source, &target)?;
+                return Ok(());
+            }
+            require_dir(&target)?;
+            let old = match atomic::exchange(&source, &target) {
+                Ok(()) => source,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::Unsupported | std::io::ErrorKind::InvalidInput
+                    ) =>
+                {
+                    let aside = atomic::temp_sibling(&target);
+                    std::fs::rename(&target, &aside)?;
+                    if let Err(err) = std::fs::rename(&source, &target) {
+                        let _ = std::fs::rename(&aside, &target);
+                        return Err(err.into());
+                    }
+                    aside
+                }
+                Err(err) => return Err(err.into()),
+            };
+            remove_dir_contents(root, &old)?;
+            std::fs::remove_dir(&old)?;
+            Ok(())
+        })
+        .map_err(to_py_err)
+    }
+
+    /// Move a file or directory into a trash directory inside the jail.
+    ///
+    /// The entry is renamed (not copied) into `trash_dir`, which is created
+    /// if missing, under a name prefixed with the current time so repeated
+    /// deletions of the same name never collide. A symlink is moved as a link.
+    ///
+    /// Args:
+    ///     path: Relative path to the entry to trash
+    ///     trash_dir: Relative path to the trash directory (default: ".trash")
+    ///
+    /// Returns:
+    ///     Relative path of the trashed entry, for use with `restore`
+    ///
+    /// Raises:
+    ///     ValueError: If either path would escape the jail, path is the jail
+    ///         root, or trash_dir is inside path
+    ///     ProtectedPathError: If path is inside, or contains, a protected subtree, or
+    ///         trash_dir is protected
+    ///     FileNotFoundError: If path does not exist
+    #[pyo3(signature = (path, trash_dir = None))]
+    fn trash(
+        &self,
+        path: &Bound<'_, PyAny>,
+        trash_dir: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<String> {
+        let source = self.locate(path)?;
+        if source == self.inner.root() {
+            return Err(PyValueError::new_err("cannot trash the jail root"));
+        }
+        let trash = match trash_dir {
+            Some(trash_dir) => self.resolve(trash_dir)?,
+            None => self.inner.join(".trash").map_err(to_py_err)?,
+        };
+        if trash.starts_with(&source) {
+            return Err(PyValueError::new_err(format!(
+                "cannot move '{}' into its own trash directory",
+                self.strip_root(&source).display()
+            )));
+        }
+        self.check_unprotected(&source, true)?;
+        self.check_unprotected(&trash, false)?;
+        std::fs::symlink_metadata(&source)?;
+        std::fs::create_dir_all(&trash)?;
+
+        let name = source.file_name().unwrap_or_default().to_string_lossy();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let stamp = format!("{}.{:09}", now.as_secs(), now.subsec_nanos());
+        let mut target = trash.join(format!("{}-{}", stamp, name));
+        let mut attempt = 1;
+        while target.symlink_metadata().is_ok() {
+            target = trash.join(format!("{}-{}-{}", stamp, attempt, name));
+            attempt += 1;
+        }
+        std::fs::rename(&source, &target)?;
+        Ok(path_to_string(self.strip_root(&target).to_path_buf()))
+    }
+
+    /// Move a trashed entry back to a location inside the jail.
+    ///
+    /// Missing parent directories of `original` are created. An existing
+    /// entry at `original` is never overwritten.
+    ///
+    /// Args:
+    ///     trashed: Relative path returned by `trash`
+    ///     original: Relative path to restore the entry to
+    ///
+    /// Raises:
+    ///     ValueError: If either path would escape the jail
+    ///     ProtectedPathError: If trashed is inside, or contains, a protected subtree, or
+    ///         original is protected
+    ///     FileExistsError: If original already exists
+    ///     FileNotFoundError: If trashed does not exist
+    fn restore(&self, trashed: &Bound<'_, PyAny>, original: &Bound<'_, PyAny>) -> PyResult<()> {
+        let source = self.locate(trashed)?;
+        let target = self.locate(original)?;
+        self.check_unprotected(&source, true)?;
+        self.check_unprotected(&target, false)?;
+        std::fs::symlink_metadata(&source)?;
+        if target.symlink_metadata().is_ok() {
+            return Err(pyo3::exceptions::PyFileExistsError::new_err(format!(
+                "'{}' already exists",
+                self.strip_root(&target).display()
+            )));
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&source, &target)?;
+        Ok(())
+    }
+
+    /// Total the size of files under a directory, grouped by extension.
+    ///
+    /// Walks the subtree in Rust with the GIL released. Only regular files
+    /// are counted; symlinks are not followed, so nothing is counted twice,
+    /// and symlinks escaping the jail are pruned.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory (default: jail root)
+    ///
+    /// Returns:
+    ///     Dict mapping lowercased extension (with leading ".", or "" for
+    ///     files without one) to total size in bytes
+    ///
+    /// Raises:
+    ///     ValueError: If path (or a directory below it) would escape the jail
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path = None))]
+    fn size_by_extension(
+        &self,
+        py: Python<'_>,
+        path: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<HashMap<String, u64>> {
+        let dir = match path {
+            Some(path) => self.resolve(path)?,
+            None => self.inner.root().to_path_buf(),
+        };
+        let root = self.inner.root();
+        py.allow_threads(|| -> Result<_, JailError> {
+            let mut totals = HashMap::new();
+            for entry in walk::walk(root, &dir)? {
+                if !entry.file_type.is_file() {
+                    continue;
+                }
+                let extension = entry
+                    .path
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+                    .unwrap_or_default();
+                *totals.entry(extension).or_insert(0) += entry.path.symlink_metadata()?.len();
+            }
+            Ok(totals)
+        })
+        .map_err(to_py_err)
+    }
+
+    /// Recreate a directory tree by hard-linking its files.
+    ///
+    /// Walks `src_root` in Rust with the GIL released. Directories are
+    /// created under `dst_root`, regular files are hard-linked so the copy
+    /// shares storage with the original, and symlinks are recreated with the
+    /// same target. Symlinks escaping the jail are pruned from the walk, and
+    /// a recreated symlink whose target would land outside the jail raises.
+    /// Other file types (FIFOs, sockets, devices) are skipped.
+    ///
+    /// Args:
+    ///     src_root: Relative path to the source directory
+    ///     dst_root: Relative path to the destination directory (created if
+    ///         missing)
+    ///
+    /// Returns:
+    ///     Number of files hard-linked
+    ///
+    /// Raises:
+    ///     ValueError: If either root, or a recreated symlink, would escape
+    ///         the jail
+    ///     ProtectedPathError: If dst_root is inside, or contains, a protected subtree
+    ///     OSError: If a destination entry already exists, or the two roots
+    ///         are on different filesystems
+    fn hardlink_tree(
+        &self,
+        py: Python<'_>,
+        src_root: &Bound<'_, PyAny>,
+        dst_root: &Bound<'_, PyAny>,
+    ) -> PyResult<u64> {
+        let src = self.resolve(src_root)?;
+        let dst = self.resolve(dst_root)?;
+        self.check_unprotected(&dst, true)?;
+        let root = self.inner.root();
+        py.allow_threads(|| -> Result<u64, JailError> {
+            let entries = walk::walk(root, &src)?;
+            std::fs::create_dir_all(&dst)?;
+            let mut linked = 0;
+            for entry in entries {
+                let target = dst.join(entry.path.strip_prefix(&src).unwrap_or(&entry.path));
+                if entry.file_type.is_dir() {
+                    std::fs::create_dir(&target)?;
+                } else if entry.file_type.is_file() {
+                    std::fs::hard_link(&entry.path, &target).map_err(|err| {
+                        if err.kind() == std::io::ErrorKind::CrossesDevices {
+                            std::io::Error::new(
+                                err.kind(),
+                                format!(
+                                    "cannot hard-link '{}' to '{}': different filesystems",
+                                    entry.path.display(),
+                                    target.display()
+                                ),
+                            )
+                        } else {
+                            err
+                        }
+                    })?;
+                    linked += 1;
+                } else if entry.file_type.is_symlink() {
+                    let link = std::fs::read_link(&entry.path)?;
+                    let parent = target.parent().unwrap_or(root);
+                    let lands = normalize_lexically(&parent.join(&link));
+                    if !lands.starts_with(root) {
+                        return Err(JailError::EscapedRoot {
+                            attempted: lands,
+                            root: root.to_path_buf(),
+                        });
+                    }
+                    create_symlink(&link, &target, entry.path.is_dir())?;
+                }
+            }
+            Ok(linked)
+        })
+        .map_err(to_py_err)
+    }
+
+    /// Build a manifest of every regular file under a directory.
+    ///
+    /// Walks the subtree and hashes each file in Rust with the GIL released.
+    /// Symlinks are not followed, and symlinks escaping the jail are pruned.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory (default: jail root)
+    ///     algo: Hash algorithm, "sha256" or "sha512" (default: "sha256")
+    ///
+    /// Returns:
+    ///     List of (relpath, size, mtime, hash) tuples sorted by relpath,
+    ///     where relpath is relative to `path` with `/` separators
+    ///
+    /// Raises:
+    ///     ValueError: If path (or a directory below it) would escape the
+    ///         jail, or algo is not supported
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path = None, algo = "sha256"))]
+    fn manifest(
+        &self,
+        py: Python<'_>,
+        path: Option<&Bound<'_, PyAny>>,
+        algo: &str,
+    ) -> PyResult<Vec<(String, u64, f64, String)>> {
+        let dir = match path {
+            Some(path) => self.resolve(path)?,
+            None => self.inner.root().to_path_buf(),
+        };
+        let digest: fn(&Path) -> std::io::Result<String> = match algo {
+            "sha256" => file_digest::<Sha256>,
+            "sha512" => file_digest::<Sha512>,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unsupported hash algorithm '{}' (expected 'sha256' or 'sha512')",
+                    algo
+                )))
+            }
+        };
+        let root = self.inner.root();
+        py.allow_threads(|| -> Result<_, JailError> {
+            let mut records = Vec::new();
+            for entry in walk::walk(root, &dir)? {
+                if !entry.file_type.is_file() {
+                    continue;
+                }
+                let metadata = entry.path.symlink_metadata()?;
+                records.push((
+                    posix_string(entry.path.strip_prefix(&dir).unwrap_or(&entry.path)),
+                    metadata.len(),
+                    timestamp(metadata.modified()?),
+                    digest(&entry.path)?,
+                ));
+            }
+            records.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(records)
+        })
+        .map_err(to_py_err)
+    }
+
+    /// Compute a single SHA-256 fingerprint of a directory tree.
+    ///
+    /// Every directory, regular file (relative path, size and content hash)
+    /// and symlink (relative path and target) below `path` contributes, in
+    /// a canonical order sorted by relative path, so identical trees always
+    /// produce the same digest and any change flips it. Modification times
+    /// are not included. Symlinks escaping the jail are pruned. Runs with
+    /// the GIL released.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory (default: jail root)
+    ///
+    /// Returns:
+    ///     Lowercase hex SHA-256 digest
+    ///
+    /// Raises:
+    ///     ValueError: If path (or a directory below it) would escape the jail
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path = None))]
+    fn tree_hash(&self, py: Python<'_>, path: Option<&Bound<'_, PyAny>>) -> PyResult<String> {
+        let dir = match path {
+            Some(path) => self.resolve(path)?,
+            None => self.inner.root().to_path_buf(),
+        };
+        let root = self.inner.root();
+        py.allow_threads(|| -> Result<_, JailError> {
+            let mut records = Vec::new();
+            for entry in walk::walk(root, &dir)? {
+                let rel = posix_string(entry.path.strip_prefix(&dir).unwrap_or(&entry.path));
+                // NUL cannot appear in names, so fields never run together
+                let record = if entry.file_type.is_dir() {
+                    format!("d\0{}\0", rel)
+                } else if entry.file_type.is_file() {
+                    let size = entry.path.symlink_metadata()?.len();
+                    let digest = file_digest::<Sha256>(&entry.path)?;
+                    format!("f\0{}\0{}\0{}\0", rel, size, digest)
+                } else if entry.file_type.is_symlink() {
+                    let target = std::fs::read_link(&entry.path)?;
+                    format!("l\0{}\0{}\0", rel, target.to_string_lossy())
+                } else {
+                    continue;
+                };
+                records.push((rel, record));
+            }
+            records.sort();
+            let mut hasher = Sha256::new();
+            for (_, record) in records {
+                hasher.update(record.as_bytes());
+            }
+            Ok(hex_digest(&hasher.finalize()))
+        })
+        .map_err(to_py_err)
+    }
+
+    /// Atomically increment an integer counter file inside the jail.
+    ///
+    /// Opens (or creates) the file, holds an exclusive advisory lock while
+    /// reading, incrementing and rewriting the value, then releases it.
+    /// Concurrent callers using `incr` never lose updates. An empty or new
+    /// file counts as 0. The GIL is released while waiting for the lock.
+    ///
+    /// Args:
+    ///     path: Relative path to the counter file (parent must exist)
+    ///     amount: Value to add (default: 1)
+    ///
+    /// Returns:
+    ///     The new counter value
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, the file does not
+    ///         contain an integer, or the counter would overflow
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If the file cannot be opened, locked or written
+    #[pyo3(signature = (path, amount = 1))]
+    fn incr(&self, py: Python<'_>, path: &Bound<'_, PyAny>, amount: i64) -> PyResult<i64> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        py.allow_threads(|| {
+            let mut file = open_counter(&resolved)?;
+            // Released when the file is closed
+            file.lock()?;
+            bump_counter(&mut file, amount)
+        })
+    }
+
+    /// Get a counter spread over several files, for hot counters.
+    ///
+    /// The counter lives in the directory `name` (created if missing), as
+    /// one counter file per shard named `0`, `1`, ... Each `incr` locks
+    /// just one shard, so concurrent writers rarely wait on each other;
+    /// `total` pays for this by reading every shard. Shard paths are
+    /// validated here and again on every operation.
+    ///
+    /// Use the same `shards` for a counter every time it is opened: shards
+    /// beyond the count given are not included in `total`.
+    ///
+    /// Args:
+    ///     name: Relative path to the counter directory (parent must exist)
+    ///     shards: Number of shard files (default: 16)
+    ///
+    /// Returns:
+    ///     A ShardedCounter for the directory
+    ///
+    /// Raises:
+    ///     ValueError: If name or a shard would escape the jail, or shards is 0
+    ///     ProtectedPathError: If the counter is inside a protected subtree
+    ///     OSError: If the directory cannot be created
+    #[pyo3(signature = (name, shards = 16))]
+    fn sharded_counter(
+        slf: &Bound<'_, Self>,
+        name: &Bound<'_, PyAny>,
+        shards: usize,
+    ) -> PyResult<ShardedCounter> {
+        if shards == 0 {
+            return Err(PyValueError::new_err("shards must be at least 1"));
+        }
+        let jail = slf.borrow();
+        let dir = jail.resolve(name)?;
+        jail.check_unprotected(&dir, true)?;
+        match std::fs::create_dir(&dir) {
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && dir.is_dir() => {}
+            result => result?,
+        }
+        let name = extract_path(name)?;
+        let shards = (0..shards)
+            .map(|shard| name.join(shard.to_string()))
+            .collect::<Vec<_>>();
+        for shard in &shards {
+            jail.inner.join(shard).map_err(to_py_err)?;
+        }
+        Ok(ShardedCounter {
+            jail: slf.clone().unbind(),
+            shards,
+        })
+    }
+
+    /// Read, transform and atomically rewrite a file inside the jail.
+    ///
+    /// `transform` is called with the current contents (under the GIL) and
+    /// must return the new contents as bytes; the result replaces the file
+    /// via a temporary sibling and rename, so readers never see a partial
+    /// write. If `transform` raises, the file is left unchanged.
+    ///
+    /// With `lock=True`, the whole cycle runs under an exclusive advisory lock
+    /// on a sidecar `.<name>.lock` file, so concurrent `update` calls never
+    /// lose each other's changes. The sidecar is used because the rename
+    /// replaces the file itself; it is left in place afterwards.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///     transform: Callable taking the old bytes and returning new bytes
+    ///     lock: Serialize with other locked updates (default: False)
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     TypeError: If transform does not return a bytes-like object
+    ///     OSError: If the file cannot be read, locked or written
+    #[pyo3(signature = (path, transform, lock = false))]
+    fn update(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        transform: &Bound<'_, PyAny>,
+        lock: bool,
+    ) -> PyResult<()> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        // Held until dropped at the end of this call
+        let _guard = if lock {
+            Some(lock_sidecar(py, &resolved)?)
+        } else {
+            None
+        };
+        let old = py.allow_threads(|| std::fs::read(&resolved))?;
+        let new = bytes_like(&transform.call1((PyBytes::new(py, &old),))?)?;
+        py.allow_threads(|| atomic::write(&resolved, &new))?;
+        Ok(())
+    }
+
+    /// Stream a file through a transform into another file inside the jail.
+    ///
+    /// `src` is read in chunks of `chunk_size` bytes (only the last may be
+    /// shorter) and each chunk is passed to `transform` under the GIL; the
+    /// bytes it returns are written to a temporary sibling of `dst`, which
+    /// replaces `dst` by rename once the whole file has gone through. Reads
+    /// and writes release the GIL.
+    ///
+    /// `transform` may return more or fewer bytes than it was given,
+    /// including none, so it can hold back a partial line or block. After
+    /// the last chunk it is called once more with `b""`: a stateful
+    /// transform must return whatever it still holds, and a stateless one
+    /// just returns `b""`. An empty `src` gets only that final call.
+    ///
+    /// If `transform` raises, `dst` is left unchanged. `src` and `dst` may
+    /// be the same file, giving an in-place rewrite.
+    ///
+    /// Args:
+    ///     src: Relative path to the source file (must exist)
+    ///     dst: Relative path to the destination file (parent must exist)
+    ///     transform: Callable taking a chunk of bytes and returning bytes
+    ///     chunk_size: Size of the chunks passed to transform (default: 65536)
+    ///
+    /// Returns:
+    ///     Number of bytes written to dst
+    ///
+    /// Raises:
+    ///     ValueError: If either path would escape the jail, or chunk_size is 0
+    ///     ProtectedPathError: If dst is inside a protected subtree
+    ///     TypeError: If transform does not return a bytes-like object
+    ///     OSError: If src cannot be read or dst cannot be written
+    #[pyo3(signature = (src, dst, transform, chunk_size = 65536))]
+    fn transform_file(
+        &self,
+        py: Python<'_>,
+        src: &Bound<'_, PyAny>,
+        dst: &Bound<'_, PyAny>,
+        transform: &Bound<'_, PyAny>,
+        chunk_size: u64,
+    ) -> PyResult<u64> {
+        if chunk_size == 0 {
+            return Err(PyValueError::new_err("chunk_size must be positive"));
+        }
+        let source = self.resolve(src)?;
+        let target = self.resolve(dst)?;
+        self.check_unprotected(&target, false)?;
+        let mut reader = std::fs::File::open(&source)?;
+        let (temp, mut file) = atomic::create_temp(&target)?;
+        let mut written = 0;
+        let mut chunk = Vec::new();
+        let streamed = loop {
+            chunk.clear();
+            let read = py.allow_threads(|| (&mut reader).take(chunk_size).read_to_end(&mut chunk));
+            let output = match read {
+                Ok(_) => transform
+                    .call1((PyBytes::new(py, &chunk),))
+                    .and_then(|output| bytes_like(&output)),
+                Err(err) => Err(err.into()),
+            };
+            let result = output.and_then(|output| {
+                py.allow_threads(|| file.write_all(&output))?;
+                written += output.len() as u64;
+                Ok(())
+            });
+            if result.is_err() || chunk.is_empty() {
+                break result;
+            }
+        };
+        if let Err(err) = streamed {
+            drop(file);
+            let _ = std::fs::remove_file(&temp);
+            return Err(err);
+        }
+        // Close the source first, in case it is the file being replaced
+        drop(reader);
+        py.allow_threads(|| atomic::commit(&temp, file, &target))?;
+        Ok(written)
+    }
+
+    /// Return a token that changes whenever a file inside the jail changes.
+    ///
+    /// The token is derived from the file's modification time and size
+    /// (plus inode and change time on Unix), so it is cheap to compute and
+    /// needs no content hashing. Remember it when reading, then pass it to
+    /// `update_if_unchanged` to write only if nobody changed the file since.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     Opaque 64-bit integer token
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be stat'ed
+    fn version_token(&self, path: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let resolved = self.resolve(path)?;
+        Ok(version_token(&std::fs::metadata(&resolved)?)?)
+    }
+
+    /// Atomically replace a file inside the jail if its version token matches.
+    ///
+    /// The check and the write run under the same sidecar lock as
+    /// `update(lock=True)`, so concurrent compare-and-swap updates never
+    /// overwrite each other. Writers that do not take the lock can still
+    /// race with it.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///     token: Token previously returned by `version_token`
+    ///     data: New contents, as a bytes-like object
+    ///
+    /// Returns:
+    ///     True if the file was written, False if its token no longer matched
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If the file cannot be stat'ed, locked or written
+    fn update_if_unchanged(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        token: u64,
+        data: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let data = bytes_like(data)?;
+        let _guard = lock_sidecar(py, &resolved)?;
+        py.allow_threads(|| -> std::io::Result<bool> {
+            if version_token(&std::fs::metadata(&resolved)?)? != token {
+                return Ok(false);
+            }
+            atomic::write(&resolved, &data)?;
+            Ok(true)
+        })
+        .map_err(Into::into)
+    }
+
+    /// Return the last lines of a text file inside the jail.
+    ///
+    /// Reads backwards from the end in chunks, so only the tail of the file
+    /// is read, with the GIL released. Lines are split on "\n" (a preceding
+    /// "\r" is removed) and returned without line endings.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///     lines: Maximum number of lines to return (default: 100)
+    ///     encoding: Text encoding (default: "utf-8"); newline must be a
+    ///         single code unit, as in UTF-8, Latin-1, UTF-16 and UTF-32
+    ///     errors: Decoding error handler, as for `bytes.decode`
+    ///         (default: "replace")
+    ///
+    /// Returns:
+    ///     List of up to `lines` lines, oldest first
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     LookupError: If the encoding is unknown
+    ///     OSError: If the file cannot be read
+    #[pyo3(signature = (path, lines = 100, encoding = "utf-8", errors = "replace"))]
+    fn tail(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        lines: usize,
+        encoding: &str,
+        errors: &str,
+    ) -> PyResult<Vec<String>> {
+        let resolved = self.resolve(path)?;
+        if lines == 0 {
+            return Ok(Vec::new());
+        }
+        // Encode "\n" via the codec; the difference of one and two newlines
+        // drops any BOM the encoder prepends
+        let one: Vec<u8> = PyString::new(py, "\n")
+            .call_method1("encode", (encoding,))?
+            .extract()?;
+        let two: Vec<u8> = PyString::new(py, "\n\n")
+            .call_method1("encode", (encoding,))?
+            .extract()?;
+        let newline = two[one.len()..].to_vec();
+
+        let tail = py.allow_threads(|| -> std::io::Result<Vec<u8>> {
+            let mut file = std::fs::File::open(&resolved)?;
+            let len = file.metadata()?.len();
+            let start = tail_offset(&mut file, len, lines, &newline)?;
+            let mut tail = Vec::new();
+            file.seek(SeekFrom::Start(start))?;
+            // Bound the read in case the file grows meanwhile
+            file.take(len - start).read_to_end(&mut tail)?;
+            Ok(tail)
+        })?;
+
+        let text: String = PyBytes::new(py, &tail)
+            .call_method1("decode", (encoding, errors))?
+            .extract()?;
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(text
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_owned())
+            .collect())
+    }
+
+    /// Check whether a file or directory inside the jail is empty.
+    ///
+    /// A file is empty when it has zero length; a directory is empty when it
+    /// has no entries. Directory checks stop at the first entry found.
+    ///
+    /// Args:
+    ///     path: Relative path to a file or directory (must exist)
+    ///
+    /// Returns:
+    ///     True if the file or directory is empty
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or is neither a regular
+    ///         file nor a directory
+    ///     OSError: If the path cannot be read
+    fn is_empty(&self, path: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        let metadata = std::fs::metadata(&resolved)?;
+        if metadata.is_dir() {
+            Ok(std::fs::read_dir(&resolved)?.next().is_none())
+        } else if metadata.is_file() {
+            Ok(metadata.len() == 0)
+        } else {
+            Err(PyValueError::new_err(format!(
+                "'{}' is not a regular file or directory",
+                self.strip_root(&resolved).display()
+            )))
+        }
+    }
+
+    /// Count the entries of a directory inside the jail.
+    ///
+    /// Entries are counted while iterating the directory, with the GIL
+    /// released, without building a list of names. Subdirectories are not
+    /// descended into.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory (default: jail root)
+    ///
+    /// Returns:
+    ///     Number of entries, excluding "." and ".."
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path = None))]
+    fn entry_count(&self, py: Python<'_>, path: Option<&Bound<'_, PyAny>>) -> PyResult<u64> {
+        let dir = match path {
+            Some(path) => self.resolve(path)?,
+            None => self.inner.root().to_path_buf(),
+        };
+        let count = py.allow_threads(|| -> std::io::Result<u64> {
+            let mut count = 0;
+            for entry in std::fs::read_dir(&dir)? {
+                entry?;
+                count += 1;
+            }
+            Ok(count)
+        })?;
+        Ok(count)
+    }
+
+    /// List a subtree inside the jail, at most `max_depth` levels deep.
+    ///
+    /// Depth counts from `path`, not from the jail root: `max_depth=1` lists
+    /// `path` itself, and higher values pre-fetch deeper levels for a tree
+    /// view. Symlinks are listed but never descended into, and symlinks that
+    /// leave the jail (or are broken) are left out. The GIL is released
+    /// during the walk.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory
+    ///     max_depth: Number of levels to list (default: 1)
+    ///
+    /// Returns:
+    ///     Paths relative to the jail root, depth-first, sorted by name
+    ///     within each directory
+    ///
+    /// Raises:
+    ///     ValueError: If path (or a directory below it) would escape the jail
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path, max_depth = 1))]
+    fn list_tree(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        max_depth: usize,
+    ) -> PyResult<Vec<String>> {
+        let dir = self.resolve(path)?;
+        let root = self.inner.root();
+        py.allow_threads(|| -> Result<_, JailError> {
+            Ok(walk::walk_limited(root, &dir, max_depth)?
+                .into_iter()
+                .map(|entry| path_to_string(self.strip_root(&entry.path).to_path_buf()))
+                .collect())
+        })
+        .map_err(to_py_err)
+    }
+
+    /// Read a file inside the jail and verify its SHA-256 digest.
+    ///
+    /// The digest is computed while reading (with the GIL released), and the
+    /// contents are only returned if it matches, so corrupted or tampered
+    /// data never reaches the caller.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///     expected_sha256: Expected digest as 64 hex characters
+    ///
+    /// Returns:
+    ///     The file contents
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or the digest is malformed
+    ///     IntegrityError: If the contents do not match the expected digest
+    ///     OSError: If the file cannot be read
+    fn read_verified<'py>(
+        &self,
+        py: Python<'py>,
+        path: &Bound<'py, PyAny>,
+        expected_sha256: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let resolved = self.resolve(path)?;
+        let expected = expected_sha256.to_ascii_lowercase();
+        if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(PyValueError::new_err(
+                "expected_sha256 must be 64 hex characters",
+            ));
+        }
+        let (contents, actual) = py.allow_threads(|| -> std::io::Result<_> {
+            let contents = std::fs::read(&resolved)?;
+            let actual = hex_digest(&Sha256::digest(&contents));
+            Ok((contents, actual))
+        })?;
+        if actual != expected {
+            return Err(IntegrityError::new_err(format!(
+                "sha256 mismatch for '{}': expected {}, got {}",
+                self.strip_root(&resolved).display(),
+                expected,
+                actual
+            )));
+        }
+        Ok(PyBytes::new(py, &contents))
+    }
+
+    /// Create a new file inside the jail, failing if it already exists.
+    ///
+    /// The file is opened with `O_CREAT | O_EXCL`, so exactly one caller wins
+    /// a race to create it; there is no check-then-create window. Only the
+    /// parent is resolved; the final component is used as given, so any
+    /// existing entry there, including a dangling or escaping symlink,
+    /// counts as existing and is never followed.
+    ///
+    /// Args:
+    ///     path: Relative path to the new file (parent must exist)
+    ///
+    /// Returns:
+    ///     A binary file object open for writing
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     FileExistsError: If the path already exists
+    ///     OSError: If the file cannot be created
+    fn create_exclusive<'py>(
+        &self,
+        py: Python<'py>,
+        path: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let located = self.locate_parent(path)?;
+        self.check_unprotected(&located, false)?;
+        py.import("io")?.call_method1("open", (located, "xb"))
+    }
+
+    /// Read and parse a JSON file inside the jail.
+    ///
+    /// The file is read and parsed with the GIL released. Object key order is
+    /// preserved and integers of any size are supported, as with `json.load`.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///
+    /// Returns:
+    ///     The decoded Python object
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or the file is not valid
+    ///         JSON (the message includes the byte offset)
+    ///     OSError: If the file cannot be read
+    fn read_json<'py>(
+        &self,
+        py: Python<'py>,
+        path: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let resolved = self.resolve(path)?;
+        let value = py.allow_threads(|| -> PyResult<serde_json::Value> {
+            let data = std::fs::read(&resolved)?;
+            serde_json::from_slice(&data).map_err(|err| {
+                PyValueError::new_err(format!(
+                    "invalid JSON in '{}' at byte {}: {}",
+                    self.strip_root(&resolved).display(),
+                    json::error_offset(&data, &err),
+                    err
+                ))
+            })
+        })?;
+        json::to_py(py, &value)
+    }
+
+    /// Serialize an object as JSON and atomically write it inside the jail.
+    ///
+    /// The data is written to a temporary file in the same directory and
+    /// renamed over the target, so readers never see a partial file.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (parent must exist)
+    ///     obj: JSON-serializable object (dict, list, str, int, float, bool, None)
+    ///     indent: Spaces per indentation level; None for compact output
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or obj contains NaN,
+    ///         infinity or a circular reference
+    ///     RecursionError: If obj is nested more than 127 levels deep
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     TypeError: If obj is not JSON serializable
+    ///     OSError: If the file cannot be written
+    #[pyo3(signature = (path, obj, indent = None))]
+    fn write_json(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        obj: &Bound<'_, PyAny>,
+        indent: Option<usize>,
+    ) -> PyResult<()> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let value = json::from_py(obj)?;
+        py.allow_threads(|| -> PyResult<()> {
+            let data = json::to_vec(&value, indent)
+                .map_err(|e| PyValueError::new_err(format!("cannot encode JSON: {}", e)))?;
+            atomic::write(&resolved, &data)?;
+            Ok(())
+        })
+    }
+
+    /// Iterate over the records of a JSON Lines file inside the jail.
+    ///
+    /// Each line is read and parsed with the GIL released, so large files
+    /// are streamed rather than loaded whole. Blank lines are skipped.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///
+    /// Returns:
+    ///     An iterator yielding one decoded object per line
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or (while iterating) a
+    ///         line is not valid JSON (the message includes the line number)
+    ///     OSError: If the file cannot be read
+    fn read_jsonl(&self, path: &Bound<'_, PyAny>) -> PyResult<JsonlReader> {
+        let resolved = self.resolve(path)?;
+        let file = std::fs::File::open(&resolved)?;
+        Ok(JsonlReader {
+            reader: BufReader::new(file),
+            name: self.strip_root(&resolved).display().to_string(),
+            line: 0,
+        })
+    }
+
+    /// Append objects to a JSON Lines file inside the jail.
+    ///
+    /// Every object is serialized before anything is written, so a
+    /// serialization error writes nothing. The lines are then appended with a
+    /// single write under an exclusive advisory lock on the file (the GIL is
+    /// released while waiting), so concurrent `write_jsonl` calls never
+    /// interleave partial lines, however large the records. Writers that do
+    /// not lock the file get no such guarantee. The file is created if missing.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (parent must exist)
+    ///     objects: Iterable of JSON-serializable objects
+    ///
+    /// Returns:
+    ///     Number of records written
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or an object contains
+    ///         NaN, infinity or a circular reference
+    ///     RecursionError: If an object is nested more than 127 levels deep
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     TypeError: If an object is not JSON serializable
+    ///     OSError: If the file cannot be written
+    fn write_jsonl(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        objects: &Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let values = objects
+            .try_iter()?
+            .map(|obj| json::from_py(&obj?))
+            .collect::<PyResult<Vec<_>>>()?;
+        py.allow_threads(|| -> PyResult<()> {
+            let mut data = Vec::new();
+            for value in &values {
+                data.extend(
+                    json::to_vec(value, None)
+                        .map_err(|e| PyValueError::new_err(format!("cannot encode JSON: {}", e)))?,
+                );
+                data.push(b'\n');
+            }
+            let mut file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&resolved)?;
+            // Released when the file is closed
+            file.lock()?;
+            file.write_all(&data)?;
+            Ok(())
+        })?;
+        Ok(values.len())
+    }
+
+    /// Open a streaming writer that enforces a byte quota.
+    ///
+    /// Data goes to a temporary sibling of `path` and is renamed over `path`
+    /// when the writer is closed, so readers never see a partial file. The
+    /// moment a write would push the total past `max_bytes`, the temporary
+    /// file is removed and QuotaExceededError is raised; `path` is untouched.
+    ///
+    /// Args:
+    ///     path: Relative path to the destination (parent must exist)
+    ///     max_bytes: Maximum total number of bytes that may be written
+    ///
+    /// Returns:
+    ///     A QuotaWriter, usable as a context manager
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If the temporary file cannot be created
+    fn quota_writer(&self, path: &Bound<'_, PyAny>, max_bytes: u64) -> PyResult<QuotaWriter> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let temp = atomic::create_temp(&resolved)?;
+        Ok(QuotaWriter {
+            target: resolved,
+            temp: Some(temp),
+            written: 0,
+            max_bytes,
+        })
+    }
+
+    /// Open a buffered appender for high-throughput logging.
+    ///
+    /// The path is validated once, at open. Writes are buffered and appended
+    /// every `flush_every` writes (or once 64 KiB is buffered), amortizing
+    /// syscalls; `flush()`, `close()` and leaving a `with` block write out
+    /// whatever remains. The file is created if missing.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (parent must exist)
+    ///     flush_every: Number of writes to buffer between flushes
+    ///
+    /// Returns:
+    ///     An AppendWriter, usable as a context manager
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or flush_every is 0
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If the file cannot be opened
+    #[pyo3(signature = (path, flush_every = 100))]
+    fn append_writer(&self, path: &Bound<'_, PyAny>, flush_every: usize) -> PyResult<AppendWriter> {
+        if flush_every == 0 {
+            return Err(PyValueError::new_err("flush_every must be at least 1"));
+        }
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&resolved)?;
+        Ok(AppendWriter {
+            file: Some(file),
+            buffer: Vec::new(),
+            pending: 0,
+            flush_every,
+        })
+    }
+
+    /// Append to a file inside the jail, rotating it when it grows too big.
+    ///
+    /// Works like a size-based `RotatingFileHandler`: when the size cap is
+    /// hit, `path` is renamed to `path.1`, an existing `path.1` to `path.2`,
+    /// and so on, deleting whatever falls past `path.<keep>`. With `keep=0`
+    /// the full file is deleted instead. Backups live in the validated
+    /// parent directory of `path` and are handled by name: a symlink in a
+    /// backup slot is moved or deleted itself, never followed. `path` itself
+    /// must not be a symlink, so the file, its lock and its backups always
+    /// sit side by side.
+    ///
+    /// `rotate` sets when the cap is checked:
+    ///
+    /// - "before" (default): if appending would take a non-empty file past
+    ///   `max_bytes`, rotate first and write `data` to a fresh file. Files
+    ///   stay within the cap unless a single `data` is larger than it.
+    /// - "after": append, then rotate if the file now exceeds `max_bytes`.
+    ///   Records are never split from the file they fill, but backups can
+    ///   overshoot the cap by one record.
+    ///
+    /// The size check, rotation and append run under an exclusive advisory
+    /// lock on a sidecar `.<name>.lock` file (as `update(lock=True)` uses),
+    /// so concurrent callers never rotate twice or lose a record.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (created if missing)
+    ///     data: Bytes to append
+    ///     max_bytes: Size cap for the file, in bytes
+    ///     keep: Number of numbered backups to keep, at most 1000
+    ///         (default: 3)
+    ///     rotate: "before" or "after" (default: "before")
+    ///
+    /// Returns:
+    ///     True if the file was rotated
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, is the jail root or
+    ///         a symlink, max_bytes is 0, keep is over 1000, or rotate is not
+    ///         "before" or "after"
+    ///     ProtectedPathError: If path or a backup is inside a protected subtree
+    ///     OSError: If the file cannot be locked, rotated or written
+    #[pyo3(signature = (path, data, max_bytes, keep = 3, rotate = "before"))]
+    fn append_capped(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        data: &Bound<'_, PyAny>,
+        max_bytes: u64,
+        keep: u32,
+        rotate: &str,
+    ) -> PyResult<bool> {
+        let rotate_first = match rotate {
+            "before" => true,
+            "after" => false,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "rotate must be 'before' or 'after', not '{}'",
+                    rotate
+                )))
+            }
+        };
+        if max_bytes == 0 {
+            return Err(PyValueError::new_err("max_bytes must be positive"));
+        }
+        if keep > MAX_BACKUPS {
+            return Err(PyValueError::new_err(format!(
+                "keep must be at most {}",
+                MAX_BACKUPS
+            )));
+        }
+        let located = self.locate_parent(path)?;
+        if self.strip_root(&located).as_os_str().is_empty() {
+            return Err(PyValueError::new_err("cannot append to the jail root"));
+        }
+        if located.is_symlink() {
+            return Err(PyValueError::new_err(format!(
+                "cannot append to symlink '{}'",
+                self.strip_root(&located).display()
+            )));
+        }
+        self.check_unprotected(&located, false)?;
+        let name = located.file_name().unwrap_or_default().to_string_lossy();
+        let backups = (1..=keep)
+            .map(|n| {
+                let backup = located.with_file_name(format!("{}.{}", name, n));
+                self.check_unprotected(&backup, false)?;
+                Ok(backup)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let data = bytes_like(data)?;
+        let _lock = lock_sidecar(py, &located)?;
+        let rotated = py.allow_threads(|| -> std::io::Result<bool> {
+            let size = match std::fs::symlink_metadata(&located) {
+                Ok(meta) => meta.len(),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+                Err(err) => return Err(err),
+            };
+            let append = || {
+                OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(&located)?
+                    .write_all(&data)
+            };
+            let grown = size + data.len() as u64;
+            if rotate_first && size > 0 && grown > max_bytes {
+                rotate_backups(&located, &backups)?;
+                append()?;
+                return Ok(true);
+            }
+            append()?;
+            if !rotate_first && grown > max_bytes {
+                rotate_backups(&located, &backups)?;
+                return Ok(true);
+            }
+            Ok(false)
+        })?;
+        Ok(rotated)
+    }
+
+    /// Open a file inside the jail and hold an advisory lock on it.
+    ///
+    /// The lock is taken on the same descriptor the returned file uses, and
+    /// is held until the `with` block exits (or `close()` is called). The GIL
+    /// is released while waiting for the lock. Locks are advisory: they only
+    /// exclude other code that also locks the file.
+    ///
+    /// Modes that truncate on open ("w") are rejected, since truncation would
+    /// happen before the lock is held; open with "r+" and truncate instead.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///     mode: File mode, as for `open()` (default: "r+b")
+    ///     shared: Take a shared (read) lock instead of an exclusive one
+    ///
+    /// Returns:
+    ///     A LockedFile context manager yielding the file object
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or mode truncates
+    ///     ProtectedPathError: If mode can write and path is inside a protected subtree
+    ///     OSError: If the file cannot be opened or locked
+    #[pyo3(signature = (path, mode = "r+b", shared = false))]
+    fn open_locked(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        mode: &str,
+        shared: bool,
+    ) -> PyResult<LockedFile> {
+        let resolved = self.resolve(path)?;
+        if mode.contains('w') {
+            return Err(PyValueError::new_err(
+                "mode 'w' truncates before locking; use 'r+' and truncate()",
+            ));
+        }
+        if mode.contains(['+', 'a', 'x']) {
+            self.check_unprotected(&resolved, false)?;
+        }
+        let file = py.import("io")?.call_method1("open", (resolved, mode))?;
+        let os_file = borrow_file(&file)?;
+        let locked = py.allow_threads(|| {
+            if shared {
+                os_file.lock_shared()
+            } else {
+                os_file.lock()
+            }
+        });
+        if let Err(err) = locked {
+            file.call_method0("close")?;
+            return Err(err.into());
+        }
+        Ok(LockedFile {
+            file: file.unbind(),
+        })
+    }
+
+    /// Check whether a file inside the jail is currently locked.
+    ///
+    /// Probes with a non-blocking exclusive lock on a fresh descriptor and
+    /// releases it immediately if acquired. A shared or exclusive lock held
+    /// by any process (including this one, through `open_locked`) counts.
+    /// This is best-effort: advisory locks only reflect cooperating code, and
+    /// the answer can be stale as soon as it is returned.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (must exist)
+    ///
+    /// Returns:
+    ///     True if another lock on the file prevented the probe
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be opened or probed
+    fn is_locked(&self, path: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        let file = std::fs::File::open(&resolved)?;
+        match file.try_lock() {
+            Ok(()) => {
+                file.unlock()?;
+                Ok(false)
+            }
+            Err(std::fs::TryLockError::WouldBlock) => Ok(true),
+            Err(std::fs::TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    /// Iterate over fixed-size records of a binary file inside the jail.
+    ///
+    /// Reads are buffered and done with the GIL released, so large record
+    /// files are streamed rather than loaded whole.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///     record_size: Size of each record in bytes
+    ///     strict: Raise on a partial record at the end of the file instead
+    ///         of yielding it short (default: False)
+    ///
+    /// Returns:
+    ///     An iterator yielding each record as bytes
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, record_size is 0, or
+    ///         (when strict) the file ends with a partial record
+    ///     OSError: If the file cannot be read
+    #[pyo3(signature = (path, record_size, strict = false))]
+    fn read_records(
+        &self,
+        path: &Bound<'_, PyAny>,
+        record_size: usize,
+        strict: bool,
+    ) -> PyResult<RecordReader> {
+        let resolved = self.resolve(path)?;
+        if record_size == 0 {
+            return Err(PyValueError::new_err("record_size must be positive"));
+        }
+        let file = std::fs::File::open(&resolved)?;
+        Ok(RecordReader {
+            reader: BufReader::with_capacity(record_size.max(64 * 1024), file),
+            record_size,
+            strict,
+            done: false,
+        })
+    }
+
+    /// Get an accessor that re-validates a path before every operation.
+    ///
+    /// Unlike an open file, which stays bound to the file it opened, the
+    /// accessor re-runs the jail check on the current state of `path` before
+    /// each read or write, and refuses to proceed if it now escapes (e.g. a
+    /// directory was swapped for a symlink). Each operation therefore costs
+    /// a full path resolution plus an open.
+    ///
+    /// Args:
+    ///     path: Relative path inside the jail
+    ///
+    /// Returns:
+    ///     A RevalidatingPath for the path
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    fn open_revalidating(
+        slf: &Bound<'_, Self>,
+        path: &Bound<'_, PyAny>,
+    ) -> PyResult<RevalidatingPath> {
+        slf.borrow().resolve(path)?;
+        Ok(RevalidatingPath {
+            jail: slf.clone().unbind(),
+            path: extract_path(path)?,
+        })
+    }
+
+    /// Check whether two files inside the jail have different contents.
+    ///
+    /// Sizes are compared first; equal-sized files are then read in lockstep
+    /// with the GIL released, stopping at the first differing chunk. Cheaper
+    /// than hashing both files when only a yes/no answer is needed.
+    ///
+    /// Args:
+    ///     a: Relative path to the first file
+    ///     b: Relative path to the second file
+    ///
+    /// Returns:
+    ///     True if the contents differ
+    ///
+    /// Raises:
+    ///     ValueError: If either path would escape the jail
+    ///     OSError: If either file cannot be read
+    fn files_differ(
+        &self,
+        py: Python<'_>,
+        a: &Bound<'_, PyAny>,
+        b: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        let (a, b) = (self.resolve(a)?, self.resolve(b)?);
+        Ok(py.allow_threads(|| contents_differ(&a, &b))?)
+    }
+
+    /// Atomically write a file inside the jail only if its contents differ.
+    ///
+    /// The existing file is compared with `data` first (sizes, then chunks,
+    /// with the GIL released), and left untouched when they are equal, so
+    /// re-running a generator does not bump mtimes or wake file watchers.
+    /// Otherwise the data is written via a temporary sibling and rename.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (parent must exist)
+    ///     data: New contents, as a bytes-like object
+    ///
+    /// Returns:
+    ///     True if the file was written, False if it already had this content
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If the file cannot be read or written
+    fn write_if_changed(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        data: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let data = bytes_like(data)?;
+        py.allow_threads(|| -> std::io::Result<bool> {
+            match file_differs_from(&resolved, &data) {
+                Ok(false) => return Ok(false),
+                Ok(true) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            atomic::write(&resolved, &data)?;
+            Ok(true)
+        })
+        .map_err(Into::into)
+    }
+
+    /// Write several files inside the jail as a unit.
+    ///
+    /// Every destination is validated before anything is written, so one
+    /// escaping path fails the whole batch. All data is then staged in
+    /// temporary siblings and renamed into place; if a rename fails, files
+    /// already replaced get their previous contents back and files newly
+    /// created are removed. This covers errors raised during the call, not
+    /// crashes: if the process dies mid-batch, some files may be updated.
+    ///
+    /// Args:
+    ///     items: Iterable of (path, data) pairs, where data is bytes-like
+    ///
+    /// Raises:
+    ///     ValueError: If any path would escape the jail
+    ///     ProtectedPathError: If any path is inside a protected subtree
+    ///     OSError: If a file cannot be written (after rolling back)
+    fn write_batch(&self, py: Python<'_>, items: &Bound<'_, PyAny>) -> PyResult<()> {
+        let mut batch = Vec::new();
+        for item in items.try_iter()? {
+            let (path, data): (Bound<'_, PyAny>, Bound<'_, PyAny>) = item?.extract()?;
+            let resolved = self.resolve(&path)?;
+            self.check_unprotected(&resolved, false)?;
+            batch.push((resolved, bytes_like(&data)?));
+        }
+        py.allow_threads(|| atomic::write_batch(&batch))?;
+        Ok(())
+    }
+
+    /// Return an unused temporary name next to a path inside the jail.
+    ///
+    /// The name has the same `.tmp-<random>-<name>` form the built-in atomic
+    /// writers use, in the same directory as `path` (so a rename over `path`
+    /// is atomic), and no entry by that name exists when it is returned.
+    /// Nothing is created; open it exclusively (mode "x") to claim it.
+    ///
+    /// Args:
+    ///     path: Relative path to the eventual target
+    ///
+    /// Returns:
+    ///     Relative path of the temporary sibling
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or is the jail root
+    fn temp_sibling(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let resolved = self.resolve(path)?;
+        if self.strip_root(&resolved).as_os_str().is_empty() {
+            return Err(PyValueError::new_err("the jail root has no siblings"));
+        }
+        loop {
+            let temp = atomic::temp_sibling(&resolved);
+            match temp.symlink_metadata() {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    return Ok(path_to_string(self.strip_root(&temp).to_path_buf()));
+                }
+                Err(err) => return Err(err.into()),
+                Ok(_) => continue,
+            }
+        }
+    }
+
+    /// Open a file inside the jail for positional reads.
+    ///
+    /// The path is validated once at open. The returned reader has no shared
+    /// cursor, so several threads can call `read_at` on it concurrently.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///
+    /// Returns:
+    ///     A RandomAccessReader for the file
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be opened
+    fn open_ra(&self, path: &Bound<'_, PyAny>) -> PyResult<RandomAccessReader> {
+        let resolved = self.resolve(path)?;
+        Ok(RandomAccessReader {
+            file: std::fs::File::open(&resolved)?,
+        })
+    }
+
+    /// Open a possibly compressed file inside the jail for reading.
+    ///
+    /// The format is sniffed from the magic bytes (gzip, zstd or bzip2), and
+    /// the returned reader decompresses as it is read, with the GIL released.
+    /// Files in no recognized format are read unchanged.
+    ///
+    /// Args:
+    ///     path: Relative path to the file
+    ///
+    /// Returns:
+    ///     Tuple of (reader, format), where format is "gzip", "zstd",
+    ///         "bzip2" or None for uncompressed data
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If the file cannot be opened, or (while reading) the
+    ///         compressed data is corrupt
+    fn open_decompressed(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+    ) -> PyResult<(DecompressedReader, Option<&'static str>)> {
+        let resolved = self.resolve(path)?;
+        let (format, decoder) =
+            py.allow_threads(|| compress::decoder(std::fs::File::open(&resolved)?))?;
+        Ok((
+            DecompressedReader {
+                decoder: Some(decoder),
+            },
+            format,
+        ))
+    }
+
+    /// Compress data and atomically write it to a file inside the jail.
+    ///
+    /// Compression happens in Rust with the GIL released, into a temporary
+    /// sibling that is renamed over `path` once complete, so readers never
+    /// see a partial file. `data` may be a bytes-like object or a binary
+    /// file-like object, which is read in chunks so large inputs are never
+    /// fully buffered. The zstd encoder is pure Rust and only implements
+    /// level 1, so other zstd levels are rejected.
+    ///
+    /// Args:
+    ///     path: Relative path to the file (parent must exist)
+    ///     data: Bytes-like object, or file-like object with a `read` method
+    ///     format: "gzip", "zstd" or "bzip2" (default: "gzip")
+    ///     level: Compression level: 0-9 for gzip, 1-9 for bzip2, 1 for zstd
+    ///         (default: 6 for gzip and bzip2, 1 for zstd)
+    ///
+    /// Returns:
+    ///     Size of the compressed file in bytes
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail, or format or level is
+    ///         not supported
+    ///     ProtectedPathError: If path is inside a protected subtree
+    ///     OSError: If the file cannot be written
+    #[pyo3(signature = (path, data, format = "gzip", level = None))]
+    fn write_compressed(
+        &self,
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        data: &Bound<'_, PyAny>,
+        format: &str,
+        level: Option<u32>,
+    ) -> PyResult<u64> {
+        let Some(codec) = compress::Format::parse(format) else {
+            return Err(PyValueError::new_err(format!(
+                "unknown format '{}' (expected gzip, zstd or bzip2)",
+                format
+            )));
+        };
+        let levels = codec.levels();
+        let level = level.unwrap_or(codec.default_level());
+        if !levels.contains(&level) {
+            let supported = if levels.start() == levels.end() {
+                format!("only {}", levels.start())
+            } else {
+                format!("{}-{}", levels.start(), levels.end())
+            };
+            return Err(PyValueError::new_err(format!(
+                "level {} is not supported for {} ({})",
+                level, format, supported
+            )));
+        }
+        let resolved = self.resolve(path)?;
+        self.check_unprotected(&resolved, false)?;
+        let (mut stream, bytes) = if data.hasattr("read")? {
+            (Some(PyReader::new(data.clone().unbind())), Vec::new())
+        } else {
+            (None, bytes_like(data)?)
+        };
+        py.allow_threads(|| -> PyResult<u64> {
+            let (temp, mut file) = atomic::create_temp(&resolved)?;
+            let encoded = match stream.as_mut() {
+                Some(reader) => compress::encode(codec, level, reader, &mut file),
+                None => compress::encode(codec, level, bytes.as_slice(), &mut file),
+            }
+            .and_then(|()| file.metadata());
+            let size = match encoded {
+                Ok(metadata) => metadata.len(),
+                Err(err) => {
+                    drop(file);
+                    let _ = std::fs::remove_file(&temp);
+                    let source_error = stream.as_mut().and_then(|reader| reader.error.take());
+                    return Err(source_error.unwrap_or_else(|| err.into()));
+                }
+            };
+            atomic::commit(&temp, file, &resolved)?;
+            Ok(size)
+        })
+    }
+
+    /// Check whether a path would collide with an existing entry that differs
+    /// only in case.
+    ///
+    /// Reads the parent directory and compares entry names to the final
+    /// component of `path` case-insensitively. An exact match is not a
+    /// collision. This flags names that coexist on a case-sensitive
+    /// filesystem but would clash on macOS or Windows.
+    ///
+    /// Args:
+    ///     path: Relative path whose final component is checked
+    ///
+    /// Returns:
+    ///     True if another entry matches the name case-insensitively
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or has no final component
+    ///     OSError: If the parent directory cannot be read
+    fn case_collides(&self, path: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let requested = extract_path(path)?;
+        self.inner.join(&requested).map_err(to_py_err)?;
+        let Some(name) = requested.file_name() else {
+            return Err(PyValueError::new_err("path has no final component"));
+        };
+        let parent = requested.parent().unwrap_or(Path::new(""));
+        let parent = self.inner.join(parent).map_err(to_py_err)?;
+
+        let name = name.to_string_lossy();
+        let folded = name.to_lowercase();
+        let entries = match std::fs::read_dir(&parent) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry_name = entry?.file_name();
+            let entry_name = entry_name.to_string_lossy();
+            if entry_name != name && entry_name.to_lowercase() == folded {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Check whether a path is ignored by ignore files inside the jail.
+    ///
+    /// Ignore files (gitignore syntax) are looked up in every directory from
+    /// the jail root down to the path's parent. Deeper files take precedence,
+    /// and ignore files that resolve outside the jail are not consulted. As
+    /// in git, a path under an ignored directory is ignored even if a deeper
+    /// file re-includes it with `!`.
+    ///
+    /// Args:
+    ///     path: Relative path to check
+    ///     ignore_files: Names of ignore files to consult (default: [".gitignore"])
+    ///
+    /// Returns:
+    ///     True if the path is ignored
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    #[pyo3(signature = (path, ignore_files = None))]
+    fn is_ignored(
+        &self,
+        path: &Bound<'_, PyAny>,
+        ignore_files: Option<Vec<String>>,
+    ) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        let ignore_files = ignore_files.unwrap_or_else(|| vec![".gitignore".to_owned()]);
+        let rel = self.strip_root(&resolved).to_path_buf();
+        if rel.as_os_str().is_empty() {
+            return Ok(false);
+        }
+        let is_dir = resolved.is_dir();
+        let components: Vec<_> = rel.components().collect();
+
+        // One matcher per directory that may hold ignore files, from the
+        // root down to the path's parent
+        let mut matchers = Vec::with_capacity(components.len());
+        for depth in 0..components.len() {
+            let level: PathBuf = components[..depth].iter().collect();
+            let mut builder = GitignoreBuilder::new(self.inner.root().join(&level));
+            for name in &ignore_files {
+                // Only consult ignore files that stay inside the jail
+                let Ok(file) = self.inner.join(level.join(name)) else {
+                    continue;
+                };
+                let Ok(contents) = std::fs::read_to_string(&file) else {
+                    continue;
+                };
+                for line in contents.lines() {
+                    // Invalid patterns are skipped, as git does
+                    let _ = builder.add_line(Some(file.clone()), line);
+                }
+            }
+            matchers.push(
+                builder
+                    .build()
+                    .map_err(|e| PyValueError::new_err(format!("invalid ignore file: {}", e)))?,
+            );
+        }
+
+        // Check each ancestor directory from the top, then the path itself.
+        // As in git, nothing below an ignored directory can be re-included,
+        // so an ignored ancestor settles it before deeper files are read.
+        for depth in 1..=components.len() {
+            let last = depth == components.len();
+            let candidate = self
+                .inner
+                .root()
+                .join(components[..depth].iter().collect::<PathBuf>());
+            let verdict = matchers[..depth]
+                .iter()
+                .rev()
+                .map(|matcher| matcher.matched(&candidate, !last || is_dir))
+                .find(|verdict| !verdict.is_none());
+            let ignored = matches!(verdict, Some(Match::Ignore(_)));
+            if ignored || last {
+                return Ok(ignored);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Check whether a path matches caller-supplied gitignore-style patterns.
+    ///
+    /// Patterns are interpreted as if they were lines of an ignore file in
+    /// the jail root (`.dockerignore`, `.npmignore` and the like): later
+    /// patterns override earlier ones, `!` negates, a trailing `/` matches
+    /// directories only, and `**` spans directories. A path is also ignored
+    /// when one of its parent directories is.
+    ///
+    /// Args:
+    ///     path: Relative path to check
+    ///     ignore_spec: Iterable of patterns, one per ignore-file line
+    ///
+    /// Returns:
+    ///     True if the path is ignored
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail or a pattern is invalid
+    fn matches_ignore(&self, path: &Bound<'_, PyAny>, ignore_spec: Vec<String>) -> PyResult<bool> {
+        let resolved = self.resolve(path)?;
+        if self.strip_root(&resolved).as_os_str().is_empty() {
+            return Ok(false);
+        }
+        let mut builder = GitignoreBuilder::new(self.inner.root());
+        for pattern in &ignore_spec {
+            builder.add_line(None, pattern).map_err(|e| {
+                PyValueError::new_err(format!("invalid ignore pattern '{}': {}", pattern, e))
+            })?;
+        }
+        let matcher = builder
+            .build()
+            .map_err(|e| PyValueError::new_err(format!("invalid ignore spec: {}", e)))?;
+        Ok(matcher
+            .matched_path_or_any_parents(&resolved, resolved.is_dir())
+            .is_ignore())
+    }
+
+    /// List a directory inside the jail, grouped by entry type.
+    ///
+    /// Entries are classified in a single pass using the file type reported
+    /// by the directory scan. Symlinks are not followed; those whose target
+    /// resolves outside the jail (or is broken) are also listed under
+    /// "escaping". The GIL is released during the scan.
+    ///
+    /// Args:
+    ///     path: Relative path to the directory (default: jail root)
+    ///
+    /// Returns:
+    ///     Dict with "dirs", "files", "symlinks", "other" and "escaping" keys,
+    ///     each a sorted list of entry names
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the jail
+    ///     OSError: If path is not a readable directory
+    #[pyo3(signature = (path = None))]
+    fn listdir_grouped<'py>(
+        &self,
+        py: Python<'py>,
+        path: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let dir = match path {
+            Some(path) => self.resolve(path)?,
+            None => self.inner.root().to_path_buf(),
+        };
+        let root = self.inner.root();
+        let groups = py.allow_threads(|| -> std::io::Result<[Vec<String>; 5]> {
+            let (mut dirs, mut files, mut symlinks) = (Vec::new(), Vec::new(), Vec::new());
+            let (mut other, mut escaping) = (Vec::new(), Vec::new());
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let file_type = entry.file_type()?;
+                if file_type.is_symlink() {
+                    let inside = entry
+                        .path()
+                        .canonicalize()
+                        .is_ok_and(|target| target.starts_with(root));
+                    if !inside {
+                        escaping.push(name.clone());
+                    }
+                    symlinks.push(name);
+                } else if file_type.is_dir() {
+                    dirs.push(name);
+                } else if file_type.is_file() {
+                    files.push(name);
+                } else {
+                    other.push(name);
+                }
+            }
+            let mut groups = [dirs, files, symlinks, other, escaping];
+            groups.iter_mut().for_each(|names| names.sort());
+            Ok(groups)
+        })?;
+
+        let result = PyDict::new(py);
+        let keys = ["dirs", "files", "symlinks", "other", "escaping"];
+        for (key, names) in keys.into_iter().zip(groups) {
+            result.set_item(key, names)?;
+        }
+        Ok(result)
+    }
+
+    fn __repr__(&self) -> String {
         format!(
             "Jail('{}')",
             normalize_path(self.inner.root().to_owned()).display()
@@ -214,6 +4272,676 @@ impl Jail {
     }
 }
 
+/// A view of a jail scoped to `tenants/{tenant_id}/`, from `Jail.for_tenant`.
+#[pyclass]
+struct TenantJail {
+    inner: RustJail,
+    tenant_id: String,
+}
+
+impl TenantJail {
+    /// The tenant directory relative to the jail root.
+    fn prefix(&self) -> PathBuf {
+        Path::new("tenants").join(&self.tenant_id)
+    }
+
+    /// Resolve the tenant directory inside the jail (it may not exist).
+    fn base(&self) -> PyResult<PathBuf> {
+        self.inner.join(self.prefix()).map_err(to_py_err)
+    }
+
+    /// Check that a resolved path is inside the tenant directory.
+    fn confine(&self, resolved: PathBuf) -> PyResult<PathBuf> {
+        let base = self.base()?;
+        if resolved.starts_with(&base) {
+            Ok(resolved)
+        } else {
+            Err(to_py_err(JailError::EscapedRoot {
+                attempted: resolved,
+                root: base,
+            }))
+        }
+    }
+}
+
+#[pymethods]
+impl TenantJail {
+    /// The tenant identifier.
+    #[getter]
+    fn tenant_id(&self) -> &str {
+        &self.tenant_id
+    }
+
+    /// The absolute path of the tenant directory.
+    #[getter]
+    fn root(&self) -> PyResult<String> {
+        Ok(path_to_string(normalize_path(self.base()?)))
+    }
+
+    /// Safely join a path relative to the tenant directory.
+    ///
+    /// Args:
+    ///     path: Path relative to the tenant directory
+    ///
+    /// Returns:
+    ///     Absolute path inside the tenant directory
+    ///
+    /// Raises:
+    ///     ValueError: If path would escape the tenant directory
+    fn join(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let path = extract_path(path)?;
+        if path.is_absolute() {
+            return Err(PyValueError::new_err("invalid path: path must be relative"));
+        }
+        let resolved = self
+            .inner
+            .join(self.prefix().join(path))
+            .map_err(to_py_err)?;
+        Ok(path_to_string(normalize_path(self.confine(resolved)?)))
+    }
+
+    /// Verify an absolute path is inside the tenant directory.
+    ///
+    /// Args:
+    ///     path: Absolute path to verify (must exist)
+    ///
+    /// Returns:
+    ///     Canonicalized path if inside the tenant directory
+    ///
+    /// Raises:
+    ///     ValueError: If path is outside the tenant directory or not absolute
+    fn contains(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let path = extract_path(path)?;
+        let resolved = self.inner.contains(&path).map_err(to_py_err)?;
+        Ok(path_to_string(normalize_path(self.confine(resolved)?)))
+    }
+
+    /// Get the path relative to the tenant directory of an absolute path.
+    ///
+    /// Args:
+    ///     path: Absolute path inside the tenant directory (must exist)
+    ///
+    /// Returns:
+    ///     Relative path from the tenant directory
+    ///
+    /// Raises:
+    ///     ValueError: If path is outside the tenant directory
+    fn relative(&self, path: &Bound<'_, PyAny>) -> PyResult<String> {
+        let path = extract_path(path)?;
+        let resolved = self.confine(self.inner.contains(&path).map_err(to_py_err)?)?;
+        let base = self.base()?;
+        Ok(path_to_string(
+            resolved
+                .strip_prefix(&base)
+                .unwrap_or(&resolved)
+                .to_path_buf(),
+        ))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TenantJail('{}', tenant_id='{}')",
+            normalize_path(self.inner.root().to_owned()).display(),
+            self.tenant_id
+        )
+    }
+}
+
+/// A file opened by `Jail.open_locked`, holding an advisory lock.
+///
+/// Use as a context manager: entering returns the file object, and exiting
+/// releases the lock and closes the file.
+#[pyclass]
+struct LockedFile {
+    file: Py<PyAny>,
+}
+
+#[pymethods]
+impl LockedFile {
+    /// The underlying file object.
+    #[getter]
+    fn file(&self, py: Python<'_>) -> Py<PyAny> {
+        self.file.clone_ref(py)
+    }
+
+    /// Release the lock and close the file. Safe to call more than once.
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        let file = self.file.bind(py);
+        if file.getattr("closed")?.is_truthy()? {
+            return Ok(());
+        }
+        // Flush buffered writes while the lock is still held
+        file.call_method0("flush")?;
+        borrow_file(file)?.unlock()?;
+        file.call_method0("close")?;
+        Ok(())
+    }
+
+    fn __enter__(&self, py: Python<'_>) -> Py<PyAny> {
+        self.file.clone_ref(py)
+    }
+
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc: &Bound<'_, PyAny>,
+        _tb: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+}
+
+/// Iterator over fixed-size records of a file, from `Jail.read_records`.
+#[pyclass]
+struct RecordReader {
+    reader: BufReader<std::fs::File>,
+    record_size: usize,
+    strict: bool,
+    done: bool,
+}
+
+#[pymethods]
+impl RecordReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let (reader, size) = (&mut self.reader, self.record_size);
+        let record = py.allow_threads(|| -> std::io::Result<Vec<u8>> {
+            let mut record = Vec::with_capacity(size);
+            reader.take(size as u64).read_to_end(&mut record)?;
+            Ok(record)
+        })?;
+        if record.len() < self.record_size {
+            self.done = true;
+            if record.is_empty() {
+                return Ok(None);
+            }
+            if self.strict {
+                return Err(PyValueError::new_err(format!(
+                    "partial record of {} bytes at end of file (record size {})",
+                    record.len(),
+                    self.record_size
+                )));
+            }
+        }
+        Ok(Some(PyBytes::new(py, &record)))
+    }
+}
+
+/// A file opened for positional reads, from `Jail.open_ra`.
+#[pyclass(frozen)]
+struct RandomAccessReader {
+    file: std::fs::File,
+}
+
+#[pymethods]
+impl RandomAccessReader {
+    /// Read up to `length` bytes starting at `offset`.
+    ///
+    /// Uses positional reads (`pread` on Unix), so the result is short only
+    /// at end of file and concurrent calls do not interfere.
+    fn read_at<'py>(
+        &self,
+        py: Python<'py>,
+        offset: u64,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let file = &self.file;
+        let data = py.allow_threads(|| -> std::io::Result<Vec<u8>> {
+            let mut data = vec![0; length];
+            let mut filled = 0;
+            while filled < length {
+                match read_at(file, &mut data[filled..], offset + filled as u64) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            data.truncate(filled);
+            Ok(data)
+        })?;
+        Ok(PyBytes::new(py, &data))
+    }
+}
+
+/// A decompressing reader over a file, from `Jail.open_decompressed`.
+#[pyclass]
+struct DecompressedReader {
+    /// Decompressing reader, until closed.
+    decoder: Option<compress::Decoder>,
+}
+
+#[pymethods]
+impl DecompressedReader {
+    /// Read up to `size` decompressed bytes, or everything left if negative.
+    ///
+    /// Returns fewer than `size` bytes only at the end of the data.
+    #[pyo3(signature = (size = -1))]
+    fn read<'py>(&mut self, py: Python<'py>, size: i64) -> PyResult<Bound<'py, PyBytes>> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Err(PyValueError::new_err("read from closed reader"));
+        };
+        let data = py.allow_threads(|| -> std::io::Result<Vec<u8>> {
+            let mut data = Vec::new();
+            match u64::try_from(size) {
+                Ok(size) => decoder.take(size).read_to_end(&mut data)?,
+                Err(_) => decoder.read_to_end(&mut data)?,
+            };
+            Ok(data)
+        })?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Close the underlying file. Safe to call more than once.
+    fn close(&mut self) {
+        self.decoder = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc: &Bound<'_, PyAny>,
+        _tb: &Bound<'_, PyAny>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+// seek_read moves the file cursor, but every read here is positional anyway
+#[cfg(windows)]
+fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// A streaming writer with a byte quota, from `Jail.quota_writer`.
+///
+/// Closing renames the data into place; exiting a `with` block because of
+/// an exception discards it instead.
+#[pyclass]
+struct QuotaWriter {
+    target: PathBuf,
+    /// Temporary file and its path, until committed or discarded.
+    temp: Option<(PathBuf, std::fs::File)>,
+    written: u64,
+    max_bytes: u64,
+}
+
+impl QuotaWriter {
+    fn discard(&mut self) {
+        if let Some((temp, file)) = self.temp.take() {
+            drop(file);
+            let _ = std::fs::remove_file(temp);
+        }
+    }
+}
+
+impl Drop for QuotaWriter {
+    fn drop(&mut self) {
+        self.discard();
+    }
+}
+
+#[pymethods]
+impl QuotaWriter {
+    /// Number of bytes written so far.
+    #[getter]
+    fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Write bytes, raising QuotaExceededError if the quota would be exceeded.
+    ///
+    /// On QuotaExceededError the partial file is removed and the writer is
+    /// closed.
+    fn write(&mut self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<usize> {
+        let data = bytes_like(data)?;
+        if self.temp.is_none() {
+            return Err(PyValueError::new_err("write to closed quota writer"));
+        }
+        let total = self.written + data.len() as u64;
+        if total > self.max_bytes {
+            self.discard();
+            return Err(QuotaExceededError::new_err(format!(
+                "writing {} more bytes after {} would exceed the quota of {} bytes",
+                data.len(),
+                self.written,
+                self.max_bytes
+            )));
+        }
+        if let Some((_, file)) = self.temp.as_mut() {
+            py.allow_threads(|| file.write_all(&data))?;
+        }
+        self.written = total;
+        Ok(data.len())
+    }
+
+    /// Rename the written data into place. Safe to call more than once.
+    fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        if let Some((temp, file)) = self.temp.take() {
+            let target = &self.target;
+            py.allow_threads(|| atomic::commit(&temp, file, target))?;
+        }
+        Ok(())
+    }
+
+    /// Discard the written data, leaving the destination untouched.
+    fn abort(&mut self) {
+        self.discard();
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        exc_type: &Bound<'_, PyAny>,
+        _exc: &Bound<'_, PyAny>,
+        _tb: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        if exc_type.is_none() {
+            self.close(py)?;
+        } else {
+            self.discard();
+        }
+        Ok(false)
+    }
+}
+
+/// Buffered bytes after which an AppendWriter flushes regardless of count.
+const APPEND_FLUSH_BYTES: usize = 64 * 1024;
+
+/// A buffered appender with periodic flushes, from `Jail.append_writer`.
+#[pyclass]
+struct AppendWriter {
+    /// Open file, until closed.
+    file: Option<std::fs::File>,
+    buffer: Vec<u8>,
+    /// Writes buffered since the last flush.
+    pending: usize,
+    flush_every: usize,
+}
+
+impl Drop for AppendWriter {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.as_mut() {
+            let _ = file.write_all(&self.buffer);
+        }
+    }
+}
+
+#[pymethods]
+impl AppendWriter {
+    /// Buffer str or bytes, flushing every `flush_every` writes or 64 KiB.
+    fn write(&mut self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<usize> {
+        if self.file.is_none() {
+            return Err(PyValueError::new_err("write to closed append writer"));
+        }
+        let len = match data.downcast::<PyString>() {
+            Ok(text) => {
+                let text = text.to_cow()?;
+                self.buffer.extend_from_slice(text.as_bytes());
+                text.len()
+            }
+            Err(_) => {
+                let data = bytes_like(data)?;
+                self.buffer.extend_from_slice(&data);
+                data.len()
+            }
+        };
+        self.pending += 1;
+        if self.pending >= self.flush_every || self.buffer.len() >= APPEND_FLUSH_BYTES {
+            self.flush(py)?;
+        }
+        Ok(len)
+    }
+
+    /// Append all buffered data to the file now.
+    fn flush(&mut self, py: Python<'_>) -> PyResult<()> {
+        if let Some(file) = self.file.as_mut() {
+            let buffer = &self.buffer;
+            py.allow_threads(|| file.write_all(buffer))?;
+        }
+        self.buffer.clear();
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Flush remaining data and close the file. Safe to call more than once.
+    fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        let flushed = self.flush(py);
+        self.file = None;
+        flushed
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc: &Bound<'_, PyAny>,
+        _tb: &Bound<'_, PyAny>,
+    ) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+}
+
+/// A path re-validated before every access, from `Jail.open_revalidating`.
+#[pyclass]
+struct RevalidatingPath {
+    jail: Py<Jail>,
+    /// The path as given, resolved again on every operation.
+    path: PathBuf,
+}
+
+impl RevalidatingPath {
+    fn resolve(&self, py: Python<'_>) -> PyResult<PathBuf> {
+        self.jail
+            .borrow(py)
+            .inner
+            .join(&self.path)
+            .map_err(to_py_err)
+    }
+
+    fn resolve_for_write(&self, py: Python<'_>) -> PyResult<PathBuf> {
+        let resolved = self.resolve(py)?;
+        self.jail.borrow(py).check_unprotected(&resolved, false)?;
+        Ok(resolved)
+    }
+}
+
+#[pymethods]
+impl RevalidatingPath {
+    /// The absolute path the accessor currently resolves to.
+    #[getter]
+    fn path(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(path_to_string(normalize_path(self.resolve(py)?)))
+    }
+
+    /// Re-validate the path and read the whole file.
+    fn read<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let resolved = self.resolve(py)?;
+        let data = py.allow_threads(|| std::fs::read(&resolved))?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Re-validate the path and atomically replace the file with `data`.
+    fn write(&self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<()> {
+        let data = bytes_like(data)?;
+        let resolved = self.resolve_for_write(py)?;
+        py.allow_threads(|| atomic::write(&resolved, &data))?;
+        Ok(())
+    }
+
+    /// Re-validate the path and append `data`, creating the file if missing.
+    fn append(&self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<()> {
+        let data = bytes_like(data)?;
+        let resolved = self.resolve_for_write(py)?;
+        py.allow_threads(|| {
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&resolved)?
+                .write_all(&data)
+        })?;
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("RevalidatingPath('{}')", self.path.display())
+    }
+}
+
+/// A counter spread over several files, from `Jail.sharded_counter`.
+#[pyclass(frozen)]
+struct ShardedCounter {
+    jail: Py<Jail>,
+    /// Shard paths as given, resolved again on every operation.
+    shards: Vec<PathBuf>,
+}
+
+impl ShardedCounter {
+    fn resolve(&self, py: Python<'_>, shard: &Path) -> PyResult<PathBuf> {
+        let jail = self.jail.borrow(py);
+        let resolved = jail.inner.join(shard).map_err(to_py_err)?;
+        jail.check_unprotected(&resolved, false)?;
+        Ok(resolved)
+    }
+}
+
+#[pymethods]
+impl ShardedCounter {
+    /// Add `amount` to a pseudo-randomly chosen shard.
+    ///
+    /// Starting from a random shard, takes the first one whose lock is free,
+    /// waiting on the starting shard only if every shard is busy. The GIL is
+    /// released while locking and writing.
+    #[pyo3(signature = (amount = 1))]
+    fn incr(&self, py: Python<'_>, amount: i64) -> PyResult<()> {
+        let start = pick_shard(self.shards.len());
+        let order = (0..self.shards.len()).map(|i| (start + i) % self.shards.len());
+        for index in order {
+            let resolved = self.resolve(py, &self.shards[index])?;
+            let locked = py.allow_threads(|| -> PyResult<_> {
+                let mut file = open_counter(&resolved)?;
+                match file.try_lock() {
+                    Ok(()) => bump_counter(&mut file, amount).map(Some),
+                    Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+                    Err(std::fs::TryLockError::Error(err)) => Err(err.into()),
+                }
+            })?;
+            if locked.is_some() {
+                return Ok(());
+            }
+        }
+        let resolved = self.resolve(py, &self.shards[start])?;
+        py.allow_threads(|| {
+            let mut file = open_counter(&resolved)?;
+            file.lock()?;
+            bump_counter(&mut file, amount)
+        })?;
+        Ok(())
+    }
+
+    /// Sum every shard, reading each under a shared lock.
+    ///
+    /// Missing shards count as 0.
+    fn total(&self, py: Python<'_>) -> PyResult<i64> {
+        let resolved = self
+            .shards
+            .iter()
+            .map(|shard| self.resolve(py, shard))
+            .collect::<PyResult<Vec<_>>>()?;
+        py.allow_threads(|| {
+            let mut total: i64 = 0;
+            for shard in &resolved {
+                let mut file = match std::fs::File::open(shard) {
+                    Ok(file) => file,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                file.lock_shared()?;
+                total = total
+                    .checked_add(read_counter(&mut file)?)
+                    .ok_or_else(|| PyValueError::new_err("counter overflow"))?;
+            }
+            Ok(total)
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        let name = self.shards[0].parent().unwrap_or(Path::new(""));
+        format!(
+            "ShardedCounter('{}', shards={})",
+            name.display(),
+            self.shards.len()
+        )
+    }
+}
+
+/// Iterator over the records of a JSON Lines file, from `Jail.read_jsonl`.
+#[pyclass]
+struct JsonlReader {
+    reader: BufReader<std::fs::File>,
+    /// Jail-relative name, for error messages.
+    name: String,
+    line: usize,
+}
+
+#[pymethods]
+impl JsonlReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let (reader, line, name) = (&mut self.reader, &mut self.line, &self.name);
+        let value = py.allow_threads(|| -> PyResult<Option<serde_json::Value>> {
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                if reader.read_until(b'\n', &mut buf)? == 0 {
+                    return Ok(None);
+                }
+                *line += 1;
+                if buf.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return serde_json::from_slice(&buf).map(Some).map_err(|err| {
+                    PyValueError::new_err(format!(
+                        "invalid JSON in '{}' on line {}: {}",
+                        name, line, err
+                    ))
+                });
+            }
+        })?;
+        value.map(|value| json::to_py(py, &value)).transpose()
+    }
+}
+
 /// One-shot path validation.
 ///
 /// This is a convenience function for validating a single path.
@@ -261,6 +4989,31 @@ fn join(root: &Bound<'_, PyAny>, path: &Bound<'_, PyAny>) -> PyResult<String> {
 #[pymodule]
 fn path_jail(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Jail>()?;
+    m.add_class::<TenantJail>()?;
+    m.add_class::<LockedFile>()?;
+    m.add_class::<RecordReader>()?;
+    m.add_class::<JsonlReader>()?;
+    m.add_class::<RandomAccessReader>()?;
+    m.add_class::<DecompressedReader>()?;
+    m.add_class::<QuotaWriter>()?;
+    m.add_class::<AppendWriter>()?;
+    m.add_class::<RevalidatingPath>()?;
+    m.add_class::<ShardedCounter>()?;
+    m.add(
+        "OutsideSubtreeError",
+        m.py().get_type::<OutsideSubtreeError>(),
+    )?;
+    m.add("IntegrityError", m.py().get_type::<IntegrityError>())?;
+    m.add("FileTooLargeError", m.py().get_type::<FileTooLargeError>())?;
+    m.add(
+        "QuotaExceededError",
+        m.py().get_type::<QuotaExceededError>(),
+    )?;
+    m.add("StaleFileError", m.py().get_type::<StaleFileError>())?;
+    m.add(
+        "ProtectedPathError",
+        m.py().get_type::<ProtectedPathError>(),
+    )?;
     m.add_function(wrap_pyfunction!(join, m)?)?;
     Ok(())
 }
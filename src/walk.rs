@@ -0,0 +1,80 @@
+//! Recursive directory walking confined to the jail.
+
+use ::path_jail::JailError;
+use std::fs::FileType;
+use std::path::{Path, PathBuf};
+
+/// An entry found by [`walk`].
+pub(crate) struct WalkEntry {
+    /// Absolute path of the entry (symlinks are not resolved).
+    pub path: PathBuf,
+    /// Type of the entry itself, without following symlinks.
+    pub file_type: FileType,
+}
+
+/// Walk the tree below `start`, which must already be validated inside `root`.
+///
+/// Entries are returned depth-first and sorted by name within each directory,
+/// so the order is deterministic. Symlinks are never descended into, and
+/// symlinks whose target is outside `root` (or broken) are pruned. Every
+/// directory is re-verified to be inside `root` before it is read.
+pub(crate) fn walk(root: &Path, start: &Path) -> Result<Vec<WalkEntry>, JailError> {
+    walk_limited(root, start, usize::MAX)
+}
+
+/// Like [`walk`], but only descend `max_depth` levels below `start`.
+///
+/// Depth counts from `start`, not from `root`: 1 returns the entries of
+/// `start` itself, 2 adds the entries of its subdirectories, and so on.
+/// A depth of 0 returns nothing.
+pub(crate) fn walk_limited(
+    root: &Path,
+    start: &Path,
+    max_depth: usize,
+) -> Result<Vec<WalkEntry>, JailError> {
+    let mut entries = Vec::new();
+    if max_depth > 0 {
+        walk_dir(root, start, max_depth, &mut entries)?;
+    }
+    Ok(entries)
+}
+
+/// Walk `dir`, whose entries are at the last of `depth` remaining levels
+/// when `depth` is 1.
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    depth: usize,
+    out: &mut Vec<WalkEntry>,
+) -> Result<(), JailError> {
+    // Re-verify: the directory may have been swapped since it was listed
+    if !dir.canonicalize()?.starts_with(root) {
+        return Err(JailError::EscapedRoot {
+            attempted: dir.to_path_buf(),
+            root: root.to_path_buf(),
+        });
+    }
+
+    let mut children = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(|entry| entry.file_name());
+
+    for child in children {
+        let path = child.path();
+        let file_type = child.file_type()?;
+        if file_type.is_symlink()
+            && !path
+                .canonicalize()
+                .is_ok_and(|target| target.starts_with(root))
+        {
+            continue;
+        }
+        out.push(WalkEntry {
+            path: path.clone(),
+            file_type,
+        });
+        if file_type.is_dir() && depth > 1 {
+            walk_dir(root, &path, depth - 1, out)?;
+        }
+    }
+    Ok(())
+}
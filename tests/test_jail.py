@@ -1,10 +1,25 @@
+import bz2
+import gzip
+import hashlib
+import json
 import os
 import sys
 import tempfile
+import threading
+import time
 from pathlib import Path
 
 import pytest
-from path_jail import Jail, join
+from path_jail import (
+    FileTooLargeError,
+    IntegrityError,
+    Jail,
+    OutsideSubtreeError,
+    ProtectedPathError,
+    QuotaExceededError,
+    StaleFileError,
+    join,
+)
 
 # Windows extended-length path prefix
 WIN_PREFIX = "\\\\?\\"
@@ -385,3 +400,2731 @@ class TestSecurityEdgeCases:
         jail = Jail(jail_dir)
         result = jail.join("subdir/")
         assert normalize_path(result).startswith(normalize_path(jail.root))
+
+
+class TestIsIgnored:
+    """Test gitignore-style ignore evaluation."""
+
+    def test_ignored_by_root_gitignore(self, jail_dir):
+        Path(jail_dir, ".gitignore").write_text("*.log\nbuild/\n")
+        jail = Jail(jail_dir)
+        assert jail.is_ignored("debug.log")
+        assert jail.is_ignored("build/output.o")
+        assert not jail.is_ignored("src/main.py")
+
+    def test_nested_gitignore_overrides(self, jail_dir):
+        Path(jail_dir, ".gitignore").write_text("*.log\n")
+        os.makedirs(os.path.join(jail_dir, "keep"))
+        Path(jail_dir, "keep", ".gitignore").write_text("!important.log\n")
+        jail = Jail(jail_dir)
+        assert jail.is_ignored("keep/other.log")
+        assert not jail.is_ignored("keep/important.log")
+
+    def test_cannot_reinclude_under_ignored_directory(self, jail_dir):
+        Path(jail_dir, ".gitignore").write_text("build/\n")
+        os.makedirs(os.path.join(jail_dir, "build", "sub"))
+        Path(jail_dir, "build", ".gitignore").write_text("!keep.txt\n")
+        Path(jail_dir, "build", "sub", ".gitignore").write_text("!*\n")
+        jail = Jail(jail_dir)
+        assert jail.is_ignored("build/keep.txt")
+        assert jail.is_ignored("build/sub/anything.txt")
+
+    def test_custom_ignore_files(self, jail_dir):
+        Path(jail_dir, ".dockerignore").write_text("secrets/\n")
+        jail = Jail(jail_dir)
+        assert not jail.is_ignored("secrets/key.pem")
+        assert jail.is_ignored("secrets/key.pem", ignore_files=[".dockerignore"])
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.is_ignored("../outside.txt")
+
+    @skip_symlinks_on_windows
+    def test_out_of_jail_ignore_file_skipped(self, jail_dir):
+        with tempfile.TemporaryDirectory() as outside:
+            Path(outside, "rules").write_text("*\n")
+            os.symlink(os.path.join(outside, "rules"), os.path.join(jail_dir, ".gitignore"))
+            jail = Jail(jail_dir)
+            assert not jail.is_ignored("file.txt")
+
+
+class TestListdirGrouped:
+    """Test type-grouped directory listing."""
+
+    def test_groups_entries(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "sub"))
+        Path(jail_dir, "a.txt").touch()
+        Path(jail_dir, "b.txt").touch()
+        jail = Jail(jail_dir)
+        result = jail.listdir_grouped()
+        assert result["dirs"] == ["sub"]
+        assert result["files"] == ["a.txt", "b.txt"]
+        assert result["symlinks"] == []
+        assert result["escaping"] == []
+
+    def test_subdirectory(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "sub", "inner"))
+        Path(jail_dir, "sub", "file.txt").touch()
+        jail = Jail(jail_dir)
+        result = jail.listdir_grouped("sub")
+        assert result["dirs"] == ["inner"]
+        assert result["files"] == ["file.txt"]
+
+    @skip_symlinks_on_windows
+    def test_symlinks_flagged(self, jail_dir):
+        Path(jail_dir, "real.txt").touch()
+        os.symlink(os.path.join(jail_dir, "real.txt"), os.path.join(jail_dir, "inside"))
+        os.symlink("/etc", os.path.join(jail_dir, "outside"))
+        os.symlink("/nonexistent/target", os.path.join(jail_dir, "broken"))
+        jail = Jail(jail_dir)
+        result = jail.listdir_grouped()
+        assert result["files"] == ["real.txt"]
+        assert result["symlinks"] == ["broken", "inside", "outside"]
+        assert result["escaping"] == ["broken", "outside"]
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.listdir_grouped("..")
+
+    def test_not_a_directory(self, jail_dir):
+        Path(jail_dir, "file.txt").touch()
+        jail = Jail(jail_dir)
+        with pytest.raises(OSError):
+            jail.listdir_grouped("file.txt")
+
+
+class TestArchiveName:
+    """Test archive member name generation."""
+
+    def test_relative_path(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.archive_name("a/b/../c.txt") == "a/c.txt"
+
+    def test_absolute_path(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "docs"))
+        Path(jail_dir, "docs", "report.pdf").touch()
+        jail = Jail(jail_dir)
+        assert jail.archive_name(os.path.join(jail_dir, "docs", "report.pdf")) == "docs/report.pdf"
+
+    @skip_symlinks_on_windows
+    def test_symlink_keeps_own_name(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "data"))
+        Path(jail_dir, "data", "v2.csv").touch()
+        os.symlink("data/v2.csv", os.path.join(jail_dir, "latest.csv"))
+        jail = Jail(jail_dir)
+        assert jail.archive_name("latest.csv") == "latest.csv"
+        assert jail.archive_name(os.path.join(jail_dir, "latest.csv")) == "latest.csv"
+
+    @skip_symlinks_on_windows
+    def test_escaping_symlink_member_rejected_by_parent(self, jail_dir):
+        os.symlink(tempfile.gettempdir(), os.path.join(jail_dir, "out"))
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.archive_name("out/file.txt")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.archive_name("../etc/passwd")
+
+    def test_root_rejected(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="root"):
+            jail.archive_name(".")
+        with pytest.raises(ValueError, match="root"):
+            jail.archive_name(jail.root)
+
+
+class TestJoinWithin:
+    """Test join() with a required subdirectory."""
+
+    def test_inside_subtree(self, jail_dir):
+        jail = Jail(jail_dir)
+        result = jail.join("users/alice/notes.txt", within="users/alice")
+        assert paths_equal(
+            result, os.path.join(normalize_path(jail.root), "users", "alice", "notes.txt")
+        )
+
+    def test_outside_subtree_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(OutsideSubtreeError, match="not within"):
+            jail.join("users/bob/notes.txt", within="users/alice")
+
+    def test_sibling_prefix_not_within(self, jail_dir):
+        """'users/alice2' must not count as inside 'users/alice'."""
+        jail = Jail(jail_dir)
+        with pytest.raises(OutsideSubtreeError):
+            jail.join("users/alice2/notes.txt", within="users/alice")
+
+    def test_traversal_out_of_subtree(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(OutsideSubtreeError):
+            jail.join("users/alice/../bob/notes.txt", within="users/alice")
+
+    def test_subtree_error_is_value_error(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError):
+            jail.join("other.txt", within="users/alice")
+
+    def test_escape_still_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.join("../etc/passwd", within="users/alice")
+
+    def test_escaping_within_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.join("file.txt", within="../")
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Link counts are Unix only")
+class TestLinkCount:
+    """Test hard link counting."""
+
+    def test_single_link(self, jail_dir):
+        Path(jail_dir, "blob").touch()
+        jail = Jail(jail_dir)
+        assert jail.link_count("blob") == 1
+
+    def test_hard_links_counted(self, jail_dir):
+        Path(jail_dir, "blob").touch()
+        os.link(os.path.join(jail_dir, "blob"), os.path.join(jail_dir, "alias"))
+        jail = Jail(jail_dir)
+        assert jail.link_count("blob") == 2
+        assert jail.link_count("alias") == 2
+
+    def test_missing_file(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.link_count("missing")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.link_count("../etc/passwd")
+
+
+class TestIsServable:
+    """Test the static file serving verdict."""
+
+    def test_regular_file(self, jail_dir):
+        Path(jail_dir, "index.html").touch()
+        jail = Jail(jail_dir)
+        assert jail.is_servable("index.html") == (True, None)
+
+    def test_escape_is_invalid(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.is_servable("../etc/passwd") == (False, "invalid")
+
+    def test_missing(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.is_servable("missing.html") == (False, "not_found")
+
+    def test_directory(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "assets"))
+        jail = Jail(jail_dir)
+        assert jail.is_servable("assets") == (False, "not_file")
+
+    def test_hidden(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, ".git"))
+        Path(jail_dir, ".git", "config").touch()
+        Path(jail_dir, ".env").touch()
+        jail = Jail(jail_dir)
+        assert jail.is_servable(".env") == (False, "hidden")
+        assert jail.is_servable(".git/config") == (False, "hidden")
+        assert jail.is_servable(".env", allow_hidden=True) == (True, None)
+
+    def test_allow_globs(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "static"))
+        Path(jail_dir, "static", "site.css").touch()
+        Path(jail_dir, "static", "app.py").touch()
+        jail = Jail(jail_dir)
+        assert jail.is_servable("static/site.css", allow=["**/*.css"]) == (True, None)
+        assert jail.is_servable("static/app.py", allow=["**/*.css"]) == (False, "not_allowed")
+
+    def test_star_does_not_cross_directories(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "static", "private"))
+        Path(jail_dir, "static", "a.css").touch()
+        Path(jail_dir, "static", "private", "k.css").touch()
+        jail = Jail(jail_dir)
+        assert jail.is_servable("static/a.css", allow=["static/*.css"]) == (True, None)
+        assert jail.is_servable("static/private/k.css", allow=["static/*.css"]) == (
+            False,
+            "not_allowed",
+        )
+        assert jail.is_servable("static/private/k.css", allow=["static/**"]) == (True, None)
+
+    def test_null_byte_is_invalid(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.is_servable("a\x00b.css") == (False, "invalid")
+
+    def test_invalid_glob(self, jail_dir):
+        Path(jail_dir, "a.txt").touch()
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="invalid glob"):
+            jail.is_servable("a.txt", allow=["[unclosed"])
+
+
+class TestReadonly:
+    """Test the cross-platform read-only flag."""
+
+    def test_set_and_clear(self, jail_dir):
+        Path(jail_dir, "artifact.bin").touch()
+        jail = Jail(jail_dir)
+        assert not jail.is_readonly("artifact.bin")
+        jail.set_readonly("artifact.bin")
+        assert jail.is_readonly("artifact.bin")
+        jail.set_readonly("artifact.bin", readonly=False)
+        assert not jail.is_readonly("artifact.bin")
+
+    @pytest.mark.skipif(sys.platform == "win32", reason="Unix permission bits")
+    def test_clear_is_owner_only(self, jail_dir):
+        path = os.path.join(jail_dir, "artifact.bin")
+        Path(path).touch()
+        os.chmod(path, 0o444)
+        jail = Jail(jail_dir)
+        jail.set_readonly("artifact.bin", readonly=False)
+        assert os.stat(path).st_mode & 0o777 == 0o644
+
+    def test_missing_file(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.set_readonly("missing")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.set_readonly("../etc/passwd")
+        with pytest.raises(ValueError, match="escapes"):
+            jail.is_readonly("../etc/passwd")
+
+
+class TestCreatedTime:
+    """Test creation time lookup."""
+
+    def test_recent_or_unsupported(self, jail_dir):
+        before = time.time()
+        Path(jail_dir, "upload.bin").touch()
+        jail = Jail(jail_dir)
+        created = jail.created_time("upload.bin")
+        # None is allowed where the filesystem lacks birth times
+        assert created is None or abs(created - before) < 60
+
+    def test_missing_file(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.created_time("missing")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.created_time("../etc/passwd")
+
+
+class TestEmptyDir:
+    """Test emptying a directory in place."""
+
+    def test_removes_contents_keeps_dir(self, jail_dir):
+        cache = os.path.join(jail_dir, "cache")
+        os.makedirs(os.path.join(cache, "a", "b"))
+        Path(cache, "top.txt").touch()
+        Path(cache, "a", "b", "deep.txt").touch()
+        jail = Jail(jail_dir)
+        jail.empty_dir("cache")
+        assert os.path.isdir(cache)
+        assert os.listdir(cache) == []
+
+    @skip_symlinks_on_windows
+    def test_symlinks_not_followed(self, jail_dir):
+        with tempfile.TemporaryDirectory() as outside:
+            Path(outside, "precious.txt").touch()
+            cache = os.path.join(jail_dir, "cache")
+            os.makedirs(cache)
+            os.symlink(outside, os.path.join(cache, "link"))
+            jail = Jail(jail_dir)
+            jail.empty_dir("cache")
+            assert os.listdir(cache) == []
+            assert os.path.exists(os.path.join(outside, "precious.txt"))
+
+    def test_not_a_directory(self, jail_dir):
+        Path(jail_dir, "file.txt").touch()
+        jail = Jail(jail_dir)
+        with pytest.raises(OSError):
+            jail.empty_dir("file.txt")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.empty_dir("..")
+
+
+class TestCanonicalRelative:
+    """Test symlink-free relative paths."""
+
+    def test_plain_file(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "a"))
+        Path(jail_dir, "a", "f.txt").touch()
+        jail = Jail(jail_dir)
+        assert jail.canonical_relative("a/./f.txt") == os.path.join("a", "f.txt")
+
+    @skip_symlinks_on_windows
+    def test_aliases_collapse(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "2025"))
+        Path(jail_dir, "2025", "report.pdf").touch()
+        os.symlink(os.path.join(jail_dir, "2025"), os.path.join(jail_dir, "latest"))
+        jail = Jail(jail_dir)
+        direct = jail.canonical_relative("2025/report.pdf")
+        via_link = jail.canonical_relative("latest/report.pdf")
+        via_abs = jail.canonical_relative(os.path.join(jail_dir, "latest", "report.pdf"))
+        assert direct == via_link == via_abs == os.path.join("2025", "report.pdf")
+
+    def test_missing_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.canonical_relative("missing.txt")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.canonical_relative("../etc/passwd")
+
+
+class TestSizeByExtension:
+    """Test per-extension size aggregation."""
+
+    def test_groups_sizes(self, jail_dir):
+        data = os.path.join(jail_dir, "data")
+        os.makedirs(os.path.join(data, "nested"))
+        Path(data, "a.csv").write_bytes(b"x" * 10)
+        Path(data, "nested", "b.CSV").write_bytes(b"x" * 5)
+        Path(data, "c.json").write_bytes(b"x" * 3)
+        Path(data, "README").write_bytes(b"x" * 2)
+        jail = Jail(jail_dir)
+        assert jail.size_by_extension("data") == {".csv": 15, ".json": 3, "": 2}
+
+    def test_defaults_to_root(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"abc")
+        jail = Jail(jail_dir)
+        assert jail.size_by_extension() == {".txt": 3}
+
+    @skip_symlinks_on_windows
+    def test_symlinks_not_counted(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"abc")
+        os.symlink(os.path.join(jail_dir, "a.txt"), os.path.join(jail_dir, "alias.txt"))
+        os.symlink("/etc", os.path.join(jail_dir, "etc"))
+        jail = Jail(jail_dir)
+        assert jail.size_by_extension() == {".txt": 3}
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.size_by_extension("..")
+
+
+class TestIncr:
+    """Test locked counter files."""
+
+    def test_creates_and_increments(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.incr("downloads") == 1
+        assert jail.incr("downloads") == 2
+        assert jail.incr("downloads", amount=10) == 12
+        assert Path(jail_dir, "downloads").read_text() == "12"
+
+    def test_concurrent_increments(self, jail_dir):
+        jail = Jail(jail_dir)
+
+        def worker():
+            for _ in range(50):
+                jail.incr("counter")
+
+        threads = [threading.Thread(target=worker) for _ in range(4)]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        assert Path(jail_dir, "counter").read_text() == "200"
+
+    def test_invalid_contents(self, jail_dir):
+        Path(jail_dir, "counter").write_text("not a number")
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="integer"):
+            jail.incr("counter")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.incr("../counter")
+
+
+class TestTail:
+    """Test reading the end of text files."""
+
+    def test_last_lines(self, jail_dir):
+        Path(jail_dir, "app.log").write_text("one\ntwo\nthree\n")
+        jail = Jail(jail_dir)
+        assert jail.tail("app.log", lines=2) == ["two", "three"]
+
+    def test_no_trailing_newline(self, jail_dir):
+        Path(jail_dir, "app.log").write_bytes(b"one\ntwo\nthree")
+        jail = Jail(jail_dir)
+        assert jail.tail("app.log", lines=2) == ["two", "three"]
+
+    def test_fewer_lines_than_requested(self, jail_dir):
+        Path(jail_dir, "app.log").write_bytes(b"one\r\ntwo\r\n")
+        jail = Jail(jail_dir)
+        assert jail.tail("app.log") == ["one", "two"]
+
+    def test_empty_file(self, jail_dir):
+        Path(jail_dir, "app.log").touch()
+        jail = Jail(jail_dir)
+        assert jail.tail("app.log") == []
+
+    def test_large_file_across_chunks(self, jail_dir):
+        lines = [f"line {i} " + "x" * 100 for i in range(5000)]
+        Path(jail_dir, "big.log").write_text("\n".join(lines) + "\n")
+        jail = Jail(jail_dir)
+        assert jail.tail("big.log", lines=1000) == lines[-1000:]
+
+    def test_utf16(self, jail_dir):
+        Path(jail_dir, "wide.log").write_bytes("α\nβ\n\u0a0a\n".encode("utf-16"))
+        jail = Jail(jail_dir)
+        assert jail.tail("wide.log", lines=2, encoding="utf-16") == ["β", "\u0a0a"]
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.tail("../etc/passwd")
+
+
+class TestBuildTree:
+    """Test nesting flat path lists into trees."""
+
+    def test_nested(self, jail_dir):
+        jail = Jail(jail_dir)
+        tree = jail.build_tree(["docs/a.md", "docs/img/logo.png", "README.md"])
+        assert tree == {"README.md": None, "docs": {"a.md": None, "img": {"logo.png": None}}}
+
+    def test_normalizes_inputs(self, jail_dir):
+        jail = Jail(jail_dir)
+        tree = jail.build_tree(["a/./b.txt", "x/../a/c.txt", Path("a") / "d.txt"])
+        assert tree == {"a": {"b.txt": None, "c.txt": None, "d.txt": None}}
+
+    def test_empty(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.build_tree([]) == {}
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.build_tree(["ok.txt", "../etc/passwd"])
+
+
+class TestIsEmpty:
+    """Test emptiness checks for files and directories."""
+
+    def test_files(self, jail_dir):
+        Path(jail_dir, "empty.txt").touch()
+        Path(jail_dir, "full.txt").write_text("data")
+        jail = Jail(jail_dir)
+        assert jail.is_empty("empty.txt")
+        assert not jail.is_empty("full.txt")
+
+    def test_directories(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "empty"))
+        os.makedirs(os.path.join(jail_dir, "full"))
+        Path(jail_dir, "full", "f").touch()
+        jail = Jail(jail_dir)
+        assert jail.is_empty("empty")
+        assert not jail.is_empty("full")
+
+    def test_missing(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.is_empty("missing")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.is_empty("..")
+
+
+class TestReadVerified:
+    """Test digest-verified reads."""
+
+    def test_matching_digest(self, jail_dir):
+        Path(jail_dir, "blob").write_bytes(b"payload")
+        digest = hashlib.sha256(b"payload").hexdigest()
+        jail = Jail(jail_dir)
+        assert jail.read_verified("blob", digest) == b"payload"
+        assert jail.read_verified("blob", digest.upper()) == b"payload"
+
+    def test_mismatch_raises(self, jail_dir):
+        Path(jail_dir, "blob").write_bytes(b"tampered")
+        digest = hashlib.sha256(b"payload").hexdigest()
+        jail = Jail(jail_dir)
+        with pytest.raises(IntegrityError, match=digest):
+            jail.read_verified("blob", digest)
+
+    def test_malformed_digest(self, jail_dir):
+        Path(jail_dir, "blob").write_bytes(b"payload")
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="64 hex"):
+            jail.read_verified("blob", "abc")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.read_verified("../etc/passwd", "0" * 64)
+
+
+class TestCreateExclusive:
+    """Test exclusive file creation."""
+
+    def test_creates_file(self, jail_dir):
+        jail = Jail(jail_dir)
+        with jail.create_exclusive("claim") as f:
+            f.write(b"worker-1")
+        assert Path(jail_dir, "claim").read_bytes() == b"worker-1"
+
+    def test_second_caller_fails(self, jail_dir):
+        jail = Jail(jail_dir)
+        jail.create_exclusive("claim").close()
+        with pytest.raises(FileExistsError):
+            jail.create_exclusive("claim")
+
+    @skip_symlinks_on_windows
+    def test_dangling_symlink_not_followed(self, jail_dir):
+        os.symlink(os.path.join(jail_dir, "target"), os.path.join(jail_dir, "claim"))
+        jail = Jail(jail_dir)
+        with pytest.raises(FileExistsError):
+            jail.create_exclusive("claim")
+        assert not os.path.exists(os.path.join(jail_dir, "target"))
+
+    @skip_symlinks_on_windows
+    def test_existing_symlinks_count_as_existing(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "sub.txt"))
+        os.symlink("sub.txt", os.path.join(jail_dir, "to_dir"))
+        os.symlink("/etc/passwd", os.path.join(jail_dir, "to_outside"))
+        jail = Jail(jail_dir)
+        with pytest.raises(FileExistsError) as excinfo:
+            jail.create_exclusive("to_dir")
+        # The error names the link, not its target
+        assert excinfo.value.filename.endswith("to_dir")
+        with pytest.raises(FileExistsError):
+            jail.create_exclusive("to_outside")
+
+    def test_missing_parent(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.create_exclusive("missing/claim")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.create_exclusive("../claim")
+
+
+class TestJson:
+    """Test JSON sidecar reads and atomic writes."""
+
+    def test_round_trip(self, jail_dir):
+        jail = Jail(jail_dir)
+        obj = {"b": [1, 2.5, None, True], "a": {"big": 2**80, "text": "héllo"}}
+        jail.write_json("meta.json", obj)
+        result = jail.read_json("meta.json")
+        assert result == obj
+        assert list(result) == ["b", "a"]  # Key order preserved
+        assert json.loads(Path(jail_dir, "meta.json").read_text()) == obj
+
+    def test_indent(self, jail_dir):
+        jail = Jail(jail_dir)
+        jail.write_json("meta.json", {"a": 1}, indent=2)
+        assert Path(jail_dir, "meta.json").read_text() == '{\n  "a": 1\n}'
+
+    def test_overwrite_leaves_no_temp_files(self, jail_dir):
+        jail = Jail(jail_dir)
+        jail.write_json("meta.json", {"v": 1})
+        jail.write_json("meta.json", {"v": 2})
+        assert jail.read_json("meta.json") == {"v": 2}
+        assert os.listdir(jail_dir) == ["meta.json"]
+
+    def test_invalid_json_reports_offset(self, jail_dir):
+        Path(jail_dir, "bad.json").write_text('{"a": 1,\n "b": }')
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="at byte 15"):
+            jail.read_json("bad.json")
+
+    def test_unserializable(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(TypeError, match="set"):
+            jail.write_json("meta.json", {"a": {1, 2}})
+        with pytest.raises(TypeError, match="keys must be str"):
+            jail.write_json("meta.json", {1: "a"})
+        with pytest.raises(ValueError, match="NaN"):
+            jail.write_json("meta.json", float("nan"))
+        assert not os.path.exists(os.path.join(jail_dir, "meta.json"))
+
+    def test_circular_reference(self, jail_dir):
+        jail = Jail(jail_dir)
+        obj = {}
+        obj["self"] = obj
+        with pytest.raises(ValueError, match="Circular reference"):
+            jail.write_json("meta.json", obj)
+        items = []
+        items.append((items,))
+        with pytest.raises(ValueError, match="Circular reference"):
+            jail.write_json("meta.json", items)
+        # Shared (but not circular) references are fine
+        shared = [1]
+        jail.write_json("meta.json", [shared, shared])
+        assert jail.read_json("meta.json") == [[1], [1]]
+
+    def test_nesting_depth_limit(self, jail_dir):
+        jail = Jail(jail_dir)
+        obj = []
+        for _ in range(126):
+            obj = [obj]
+        # 127 levels is the deepest that can be read back
+        jail.write_json("meta.json", obj)
+        assert jail.read_json("meta.json") == obj
+        with pytest.raises(RecursionError):
+            jail.write_json("meta.json", [obj])
+        with pytest.raises(RecursionError):
+            jail.write_json("meta.json", {"a": [obj]})
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.read_json("../meta.json")
+        with pytest.raises(ValueError, match="escapes"):
+            jail.write_json("../meta.json", {})
+
+
+class TestJoinWithLimit:
+    """Test join() with a size precondition."""
+
+    def test_missing_file_allowed(self, jail_dir):
+        jail = Jail(jail_dir)
+        result = jail.join_with_limit("new.bin", max_bytes=10)
+        assert paths_equal(result, os.path.join(normalize_path(jail.root), "new.bin"))
+
+    def test_small_file_allowed(self, jail_dir):
+        Path(jail_dir, "small.bin").write_bytes(b"x" * 10)
+        jail = Jail(jail_dir)
+        assert jail.join_with_limit("small.bin", max_bytes=10).endswith("small.bin")
+
+    def test_large_file_rejected(self, jail_dir):
+        Path(jail_dir, "big.bin").write_bytes(b"x" * 11)
+        jail = Jail(jail_dir)
+        with pytest.raises(FileTooLargeError, match="11 bytes"):
+            jail.join_with_limit("big.bin", max_bytes=10)
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.join_with_limit("../big.bin", max_bytes=10)
+
+
+class TestOpenLocked:
+    """Test lock-and-open context manager."""
+
+    def test_read_modify_write(self, jail_dir):
+        Path(jail_dir, "state").write_bytes(b"1")
+        jail = Jail(jail_dir)
+        with jail.open_locked("state") as f:
+            value = int(f.read())
+            f.seek(0)
+            f.truncate()
+            f.write(str(value + 1).encode())
+        assert Path(jail_dir, "state").read_bytes() == b"2"
+
+    def test_exclusive_lock_blocks(self, jail_dir):
+        Path(jail_dir, "state").touch()
+        jail = Jail(jail_dir)
+        acquired = threading.Event()
+
+        def contender():
+            with jail.open_locked("state"):
+                acquired.set()
+
+        with jail.open_locked("state"):
+            t = threading.Thread(target=contender)
+            t.start()
+            assert not acquired.wait(0.2)
+        t.join(5)
+        assert acquired.is_set()
+
+    def test_shared_locks_coexist(self, jail_dir):
+        Path(jail_dir, "state").touch()
+        jail = Jail(jail_dir)
+        with jail.open_locked("state", mode="rb", shared=True) as a:
+            with jail.open_locked("state", mode="rb", shared=True) as b:
+                assert a.read() == b.read() == b""
+
+    def test_close_is_idempotent(self, jail_dir):
+        Path(jail_dir, "state").touch()
+        jail = Jail(jail_dir)
+        locked = jail.open_locked("state")
+        locked.close()
+        locked.close()
+        assert locked.file.closed
+
+    def test_truncating_mode_rejected(self, jail_dir):
+        Path(jail_dir, "state").write_bytes(b"keep")
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="truncates"):
+            jail.open_locked("state", mode="wb")
+        assert Path(jail_dir, "state").read_bytes() == b"keep"
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.open_locked("../state")
+
+
+class TestReadRecords:
+    """Test fixed-size record iteration."""
+
+    def test_exact_records(self, jail_dir):
+        Path(jail_dir, "data.bin").write_bytes(b"aaaabbbbcccc")
+        jail = Jail(jail_dir)
+        assert list(jail.read_records("data.bin", 4)) == [b"aaaa", b"bbbb", b"cccc"]
+
+    def test_short_final_record(self, jail_dir):
+        Path(jail_dir, "data.bin").write_bytes(b"aaaabb")
+        jail = Jail(jail_dir)
+        assert list(jail.read_records("data.bin", 4)) == [b"aaaa", b"bb"]
+
+    def test_strict_partial_raises(self, jail_dir):
+        Path(jail_dir, "data.bin").write_bytes(b"aaaabb")
+        jail = Jail(jail_dir)
+        records = jail.read_records("data.bin", 4, strict=True)
+        assert next(records) == b"aaaa"
+        with pytest.raises(ValueError, match="partial record of 2 bytes"):
+            next(records)
+
+    def test_empty_file(self, jail_dir):
+        Path(jail_dir, "data.bin").touch()
+        jail = Jail(jail_dir)
+        assert list(jail.read_records("data.bin", 4)) == []
+
+    def test_zero_record_size(self, jail_dir):
+        Path(jail_dir, "data.bin").touch()
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="positive"):
+            jail.read_records("data.bin", 0)
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.read_records("../data.bin", 4)
+
+
+class TestCaseCollides:
+    """Test case-insensitive collision detection."""
+
+    def test_case_variant_collides(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "docs"))
+        Path(jail_dir, "docs", "report.pdf").touch()
+        jail = Jail(jail_dir)
+        assert jail.case_collides("docs/Report.PDF")
+
+    def test_exact_match_is_not_collision(self, jail_dir):
+        Path(jail_dir, "report.pdf").touch()
+        jail = Jail(jail_dir)
+        assert not jail.case_collides("report.pdf")
+
+    def test_unrelated_names(self, jail_dir):
+        Path(jail_dir, "other.pdf").touch()
+        jail = Jail(jail_dir)
+        assert not jail.case_collides("report.pdf")
+
+    def test_missing_parent(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert not jail.case_collides("missing/report.pdf")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.case_collides("../report.pdf")
+
+
+class TestRelativeOfAbsolute:
+    """Test lexical relative paths from absolute inputs."""
+
+    def test_lexical_nonexistent(self, jail_dir):
+        jail = Jail(jail_dir)
+        path = os.path.join(jail.root, "cache", "..", "data", "new.db")
+        assert jail.relative_of_absolute(path) == os.path.join("data", "new.db")
+
+    def test_lexical_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.relative_of_absolute(os.path.join(jail.root, "..", "other"))
+
+    def test_relative_input_rejected(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="absolute"):
+            jail.relative_of_absolute("data/new.db")
+
+    @skip_symlinks_on_windows
+    def test_lexical_does_not_resolve_symlinks(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "real"))
+        Path(jail_dir, "real", "f.txt").touch()
+        os.symlink(os.path.join(jail_dir, "real"), os.path.join(jail_dir, "alias"))
+        jail = Jail(jail_dir)
+        path = os.path.join(jail.root, "alias", "f.txt")
+        assert jail.relative_of_absolute(path) == os.path.join("alias", "f.txt")
+        assert jail.relative_of_absolute(path, resolve=True) == os.path.join("real", "f.txt")
+
+
+class TestListdirExt:
+    """Test extension-filtered directory listing."""
+
+    def test_filters_case_insensitively(self, jail_dir):
+        gallery = os.path.join(jail_dir, "gallery")
+        os.makedirs(gallery)
+        for name in ["a.jpg", "b.PNG", "c.txt", "noext"]:
+            Path(gallery, name).touch()
+        jail = Jail(jail_dir)
+        assert jail.listdir_ext("gallery", extensions={".jpg", ".png"}) == ["a.jpg", "b.PNG"]
+
+    def test_dot_optional(self, jail_dir):
+        Path(jail_dir, "a.jpg").touch()
+        jail = Jail(jail_dir)
+        assert jail.listdir_ext(extensions={"JPG"}) == ["a.jpg"]
+
+    def test_directories_excluded(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "album.jpg"))
+        jail = Jail(jail_dir)
+        assert jail.listdir_ext(extensions={".jpg"}) == []
+
+    @skip_symlinks_on_windows
+    def test_symlinks_only_to_files_inside(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "sub.txt"))
+        Path(jail_dir, "real.txt").touch()
+        os.symlink("sub.txt", os.path.join(jail_dir, "link.txt"))
+        os.symlink("missing", os.path.join(jail_dir, "dangle.txt"))
+        os.symlink("/etc/hostname", os.path.join(jail_dir, "out.txt"))
+        os.symlink("real.txt", os.path.join(jail_dir, "alias.txt"))
+        jail = Jail(jail_dir)
+        assert jail.listdir_ext(extensions={".txt"}) == ["alias.txt", "real.txt"]
+
+    def test_multi_part_suffix(self, jail_dir):
+        for name in ["a.tar.gz", "b.TAR.GZ", "c.gz", "d.tar", ".tar.gz"]:
+            Path(jail_dir, name).touch()
+        jail = Jail(jail_dir)
+        assert jail.listdir_ext(extensions={".tar.gz"}) == ["a.tar.gz", "b.TAR.GZ"]
+        # A dot file counts when a stem remains before the suffix
+        assert jail.listdir_ext(extensions={"gz"}) == [".tar.gz", "a.tar.gz", "b.TAR.GZ", "c.gz"]
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.listdir_ext("..", extensions={".jpg"})
+
+
+class TestResolutionDelta:
+    """Test lexical vs physical path resolution."""
+
+    def test_plain_path_matches(self, jail_dir):
+        jail = Jail(jail_dir)
+        lexical, physical = jail.resolution_delta("a/./b/../c.txt")
+        assert lexical == physical == os.path.join("a", "c.txt")
+
+    @skip_symlinks_on_windows
+    def test_symlink_differs(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "real"))
+        os.symlink(os.path.join(jail_dir, "real"), os.path.join(jail_dir, "link"))
+        jail = Jail(jail_dir)
+        lexical, physical = jail.resolution_delta("link/file.txt")
+        assert lexical == os.path.join("link", "file.txt")
+        assert physical == os.path.join("real", "file.txt")
+
+    def test_lexical_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.resolution_delta("../outside")
+
+    @skip_symlinks_on_windows
+    def test_physical_escape_raises(self, jail_dir):
+        outside = tempfile.mkdtemp()
+        os.symlink(outside, os.path.join(jail_dir, "out"))
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.resolution_delta("out/file.txt")
+
+
+class TestOpenRa:
+    """Test positional reads."""
+
+    def test_read_at(self, jail_dir):
+        Path(jail_dir, "data.bin").write_bytes(b"0123456789")
+        reader = Jail(jail_dir).open_ra("data.bin")
+        assert reader.read_at(3, 4) == b"3456"
+        assert reader.read_at(0, 2) == b"01"
+
+    def test_short_at_eof(self, jail_dir):
+        Path(jail_dir, "data.bin").write_bytes(b"0123456789")
+        reader = Jail(jail_dir).open_ra("data.bin")
+        assert reader.read_at(8, 10) == b"89"
+        assert reader.read_at(20, 4) == b""
+
+    def test_concurrent_reads(self, jail_dir):
+        data = bytes(range(256)) * 64
+        Path(jail_dir, "data.bin").write_bytes(data)
+        reader = Jail(jail_dir).open_ra("data.bin")
+        results = {}
+
+        def worker(offset):
+            results[offset] = reader.read_at(offset, 256)
+
+        threads = [threading.Thread(target=worker, args=(i * 256,)) for i in range(64)]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        assert all(results[i * 256] == data[i * 256 : (i + 1) * 256] for i in range(64))
+
+    def test_missing_file(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).open_ra("missing.bin")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).open_ra("../outside.bin")
+
+
+class TestHardlinkTree:
+    """Test hard-link snapshots of a subtree."""
+
+    def test_links_files_and_dirs(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "src", "sub"))
+        Path(jail_dir, "src", "a.txt").write_text("a")
+        Path(jail_dir, "src", "sub", "b.txt").write_text("b")
+        jail = Jail(jail_dir)
+        assert jail.hardlink_tree("src", "snap") == 2
+        assert Path(jail_dir, "snap", "sub", "b.txt").read_text() == "b"
+        assert os.path.samefile(
+            os.path.join(jail_dir, "src", "a.txt"), os.path.join(jail_dir, "snap", "a.txt")
+        )
+
+    @skip_symlinks_on_windows
+    def test_recreates_symlinks(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "src"))
+        Path(jail_dir, "src", "a.txt").write_text("a")
+        os.symlink("a.txt", os.path.join(jail_dir, "src", "link"))
+        jail = Jail(jail_dir)
+        jail.hardlink_tree("src", "snap")
+        assert os.readlink(os.path.join(jail_dir, "snap", "link")) == "a.txt"
+
+    @skip_symlinks_on_windows
+    def test_relocated_symlink_escape_raises(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "src"))
+        Path(jail_dir, "target.txt").write_text("t")
+        os.symlink("../target.txt", os.path.join(jail_dir, "src", "link"))
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.hardlink_tree("src", ".")
+
+    def test_existing_destination_file_raises(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "src"))
+        os.makedirs(os.path.join(jail_dir, "snap"))
+        Path(jail_dir, "src", "a.txt").write_text("a")
+        Path(jail_dir, "snap", "a.txt").write_text("old")
+        jail = Jail(jail_dir)
+        with pytest.raises(OSError):
+            jail.hardlink_tree("src", "snap")
+
+    def test_escape_raises(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "src"))
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.hardlink_tree("src", "../snap")
+
+
+class TestDisplay:
+    """Test truncated display strings."""
+
+    def test_short_path_unchanged(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.display("users/alice/file.txt") == "users/alice/file.txt"
+
+    def test_middle_truncated(self, jail_dir):
+        jail = Jail(jail_dir)
+        path = "users/alice/projects/2024/reports/q3/summary.pdf"
+        assert jail.display(path, max_len=30) == "users/alice/.../summary.pdf"
+
+    def test_long_file_name_truncated(self, jail_dir):
+        jail = Jail(jail_dir)
+        result = jail.display("dir/" + "x" * 50 + ".txt", max_len=20)
+        assert len(result) == 20
+        assert "..." in result
+        assert result.endswith(".txt")
+
+    def test_tiny_max_len_respected(self, jail_dir):
+        jail = Jail(jail_dir)
+        for max_len in range(6):
+            result = jail.display("a/b/file.txt", max_len=max_len)
+            assert len(result) <= max_len
+        assert jail.display("a/b/file.txt", max_len=0) == ""
+        assert jail.display("a/b/file.txt", max_len=2) == ".."
+        assert jail.display("a/b/file.txt", max_len=4) == "f..."
+        assert jail.display("a", max_len=1) == "a"
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.display("../outside/file.txt")
+
+
+class TestManifest:
+    """Test recursive manifests with hashes."""
+
+    def test_records_sorted(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "data", "sub"))
+        Path(jail_dir, "data", "sub", "b.txt").write_bytes(b"bb")
+        Path(jail_dir, "data", "a.txt").write_bytes(b"a")
+        jail = Jail(jail_dir)
+        records = jail.manifest("data")
+        assert [r[0] for r in records] == ["a.txt", "sub/b.txt"]
+        relpath, size, mtime, digest = records[1]
+        assert size == 2
+        expected_mtime = os.path.getmtime(os.path.join(jail_dir, "data", "sub", "b.txt"))
+        assert mtime == pytest.approx(expected_mtime)
+        assert digest == hashlib.sha256(b"bb").hexdigest()
+
+    def test_sha512(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"a")
+        jail = Jail(jail_dir)
+        assert jail.manifest(algo="sha512")[0][3] == hashlib.sha512(b"a").hexdigest()
+
+    def test_unsupported_algo(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="unsupported hash algorithm"):
+            jail.manifest(algo="md5")
+
+    @skip_symlinks_on_windows
+    def test_escaping_symlink_pruned(self, jail_dir):
+        outside = tempfile.mkdtemp()
+        Path(outside, "secret.txt").write_text("secret")
+        os.symlink(outside, os.path.join(jail_dir, "out"))
+        Path(jail_dir, "a.txt").write_text("a")
+        jail = Jail(jail_dir)
+        assert [r[0] for r in jail.manifest()] == ["a.txt"]
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.manifest("..")
+
+
+class TestFileType:
+    """Test single-stat entry classification."""
+
+    def test_file_and_dir(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "sub"))
+        Path(jail_dir, "sub", "a.txt").touch()
+        jail = Jail(jail_dir)
+        assert jail.file_type("sub") == "dir"
+        assert jail.file_type("sub/a.txt") == "file"
+
+    @skip_symlinks_on_windows
+    def test_symlink_not_followed(self, jail_dir):
+        Path(jail_dir, "a.txt").touch()
+        os.symlink("a.txt", os.path.join(jail_dir, "link"))
+        jail = Jail(jail_dir)
+        assert jail.file_type("link") == "symlink"
+        assert jail.file_type("link", follow_symlinks=True) == "file"
+
+    @skip_symlinks_on_windows
+    def test_dot_dot_after_symlink_is_physical(self, jail_dir):
+        # link/.. is sub (the parent of the link's target), not the root
+        os.makedirs(os.path.join(jail_dir, "sub", "deep"))
+        os.symlink("sub/deep", os.path.join(jail_dir, "link"))
+        os.symlink("deep", os.path.join(jail_dir, "sub", "x"))
+        Path(jail_dir, "x").touch()
+        jail = Jail(jail_dir)
+        assert jail.file_type("link/../x") == "symlink"
+        assert jail.file_type("link/../x", follow_symlinks=True) == "dir"
+
+    @pytest.mark.skipif(not hasattr(os, "mkfifo"), reason="FIFOs require Unix")
+    def test_fifo(self, jail_dir):
+        os.mkfifo(os.path.join(jail_dir, "pipe"))
+        jail = Jail(jail_dir)
+        assert jail.file_type("pipe") == "fifo"
+
+    def test_missing_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.file_type("missing")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.file_type("../outside")
+
+
+class TestForTenant:
+    """Test tenant-scoped jail views."""
+
+    def test_join_prefixes_tenant(self, jail_dir):
+        tenant = Jail(jail_dir).for_tenant("acme")
+        expected = os.path.join(jail_dir, "tenants", "acme", "docs", "a.txt")
+        assert paths_equal(tenant.join("docs/a.txt"), expected)
+        assert tenant.tenant_id == "acme"
+
+    def test_other_tenant_rejected(self, jail_dir):
+        tenant = Jail(jail_dir).for_tenant("acme")
+        with pytest.raises(ValueError, match="escapes"):
+            tenant.join("../other/secret.txt")
+
+    def test_escape_raises(self, jail_dir):
+        tenant = Jail(jail_dir).for_tenant("acme")
+        with pytest.raises(ValueError, match="escapes"):
+            tenant.join("../../../etc/passwd")
+
+    def test_invalid_tenant_id(self, jail_dir):
+        jail = Jail(jail_dir)
+        for tenant_id in ["", ".", "..", "a/b", "a\\b", "a\x00b"]:
+            with pytest.raises(ValueError, match="invalid tenant id"):
+                jail.for_tenant(tenant_id)
+
+    def test_contains_and_relative(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "tenants", "acme"))
+        os.makedirs(os.path.join(jail_dir, "tenants", "other"))
+        Path(jail_dir, "tenants", "acme", "a.txt").touch()
+        Path(jail_dir, "tenants", "other", "b.txt").touch()
+        tenant = Jail(jail_dir).for_tenant("acme")
+        inside = os.path.join(jail_dir, "tenants", "acme", "a.txt")
+        assert tenant.relative(inside) == "a.txt"
+        with pytest.raises(ValueError, match="escapes"):
+            tenant.contains(os.path.join(jail_dir, "tenants", "other", "b.txt"))
+
+
+class TestTrash:
+    """Test soft-delete into a jailed trash directory."""
+
+    def test_trash_and_restore(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "docs"))
+        Path(jail_dir, "docs", "draft.md").write_text("draft")
+        jail = Jail(jail_dir)
+        trashed = jail.trash("docs/draft.md")
+        assert not os.path.exists(os.path.join(jail_dir, "docs", "draft.md"))
+        assert Path(jail_dir, trashed).read_text() == "draft"
+        jail.restore(trashed, "docs/draft.md")
+        assert Path(jail_dir, "docs", "draft.md").read_text() == "draft"
+
+    def test_same_name_does_not_collide(self, jail_dir):
+        jail = Jail(jail_dir)
+        Path(jail_dir, "a.txt").write_text("1")
+        first = jail.trash("a.txt")
+        Path(jail_dir, "a.txt").write_text("2")
+        second = jail.trash("a.txt")
+        assert first != second
+        assert Path(jail_dir, first).read_text() == "1"
+        assert Path(jail_dir, second).read_text() == "2"
+
+    def test_restore_refuses_overwrite(self, jail_dir):
+        jail = Jail(jail_dir)
+        Path(jail_dir, "a.txt").write_text("old")
+        trashed = jail.trash("a.txt")
+        Path(jail_dir, "a.txt").write_text("new")
+        with pytest.raises(FileExistsError):
+            jail.restore(trashed, "a.txt")
+
+    def test_root_and_missing_rejected(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="jail root"):
+            jail.trash(".")
+        with pytest.raises(FileNotFoundError):
+            jail.trash("missing.txt")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        Path(jail_dir, "a.txt").touch()
+        with pytest.raises(ValueError, match="escapes"):
+            jail.trash("../outside.txt")
+        with pytest.raises(ValueError, match="escapes"):
+            jail.trash("a.txt", trash_dir="../trash")
+
+
+class TestProtected:
+    """Test protected subtrees."""
+
+    def test_is_protected_component_wise(self, jail_dir):
+        jail = Jail(jail_dir, protected=["_system"])
+        assert jail.is_protected("_system")
+        assert jail.is_protected("_system/state.json")
+        assert not jail.is_protected("_system2/state.json")
+        assert not jail.is_protected("data.json")
+
+    def test_write_refused(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "_system"))
+        jail = Jail(jail_dir, protected=["_system"])
+        with pytest.raises(ProtectedPathError):
+            jail.write_json("_system/state.json", {})
+        with pytest.raises(ProtectedPathError):
+            jail.incr("_system/counter")
+        jail.write_json("state.json", {})
+
+    def test_recursive_removal_refused(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "data", "_system"))
+        jail = Jail(jail_dir, protected=["data/_system"])
+        with pytest.raises(ProtectedPathError):
+            jail.empty_dir("data")
+        with pytest.raises(ProtectedPathError):
+            jail.trash("data")
+        assert os.path.isdir(os.path.join(jail_dir, "data", "_system"))
+
+    @skip_symlinks_on_windows
+    def test_symlink_into_protected(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "_system"))
+        os.symlink(os.path.join(jail_dir, "_system"), os.path.join(jail_dir, "alias"))
+        jail = Jail(jail_dir, protected=["_system"])
+        assert jail.is_protected("alias/state.json")
+        with pytest.raises(ProtectedPathError):
+            jail.write_json("alias/state.json", {})
+
+    def test_is_value_error(self):
+        assert issubclass(ProtectedPathError, ValueError)
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir, protected=["../outside"])
+        jail = Jail(jail_dir, protected=["_system"])
+        with pytest.raises(ValueError, match="escapes"):
+            jail.is_protected("../outside")
+
+
+class TestUrlSegments:
+    """Test percent-encoded URL segments."""
+
+    def test_plain_segments(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.url_segments("a/b/c.txt") == ["a", "b", "c.txt"]
+
+    def test_reserved_characters_encoded(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.url_segments("Q3 #1/a?b%.txt") == ["Q3%20%231", "a%3Fb%25.txt"]
+
+    def test_unicode_encoded_as_utf8(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.url_segments("caf\u00e9") == ["caf%C3%A9"]
+
+    def test_root_is_empty(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.url_segments(".") == []
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.url_segments("../outside")
+
+
+class TestJoinOr:
+    """Test join with a fallback value."""
+
+    def test_valid_path_joined(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.join_or("a/b.txt", None) == jail.join("a/b.txt")
+
+    def test_escape_returns_default(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.join_or("../etc/passwd", "fallback") == "fallback"
+        assert jail.join_or("../etc/passwd", None) is None
+
+    def test_absolute_returns_default(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.join_or(os.path.abspath(os.sep), "fallback") == "fallback"
+
+    def test_dot_dot_collapsed_lexically(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.join_or("a/../b.txt", None) == jail.join("b.txt")
+        assert jail.join_or("a/../../b.txt", "fallback") == "fallback"
+        assert jail.join_or("a\x00b", "fallback") == "fallback"
+
+    @skip_symlinks_on_windows
+    def test_symlinks_not_resolved(self, jail_dir):
+        # Lexical only: no IO, so even a broken symlink yields the joined path
+        os.symlink("missing", os.path.join(jail_dir, "dangling"))
+        jail = Jail(jail_dir)
+        expected = os.path.join(normalize_path(jail.root), "dangling", "file.txt")
+        assert paths_equal(jail.join_or("dangling/file.txt", None), expected)
+
+    def test_wrong_type_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(TypeError):
+            jail.join_or(123, "fallback")
+
+
+class TestJoinFresh:
+    """Test joins that require a recent file."""
+
+    def test_fresh_file(self, jail_dir):
+        Path(jail_dir, "token").touch()
+        jail = Jail(jail_dir)
+        assert paths_equal(jail.join_fresh("token", 60), os.path.join(jail_dir, "token"))
+
+    def test_zero_age_is_stale(self, jail_dir):
+        Path(jail_dir, "token").touch()
+        time.sleep(0.05)
+        jail = Jail(jail_dir)
+        with pytest.raises(StaleFileError, match="exceeding the limit of 0 seconds"):
+            jail.join_fresh("token", 0)
+
+    def test_missing_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(FileNotFoundError):
+            jail.join_fresh("missing", 60)
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.join_fresh("../token", 60)
+
+
+class TestTreeHash:
+    """Test deterministic subtree fingerprints."""
+
+    def _make_tree(self, base):
+        os.makedirs(os.path.join(base, "sub"))
+        Path(base, "a.txt").write_text("a")
+        Path(base, "sub", "b.txt").write_text("b")
+
+    def test_identical_trees_match(self, jail_dir):
+        self._make_tree(os.path.join(jail_dir, "one"))
+        self._make_tree(os.path.join(jail_dir, "two"))
+        jail = Jail(jail_dir)
+        assert jail.tree_hash("one") == jail.tree_hash("two")
+        assert len(jail.tree_hash("one")) == 64
+
+    def test_content_change_flips_hash(self, jail_dir):
+        self._make_tree(os.path.join(jail_dir, "one"))
+        jail = Jail(jail_dir)
+        before = jail.tree_hash("one")
+        Path(jail_dir, "one", "sub", "b.txt").write_text("B")
+        assert jail.tree_hash("one") != before
+
+    def test_structure_change_flips_hash(self, jail_dir):
+        self._make_tree(os.path.join(jail_dir, "one"))
+        jail = Jail(jail_dir)
+        before = jail.tree_hash("one")
+        os.makedirs(os.path.join(jail_dir, "one", "empty"))
+        assert jail.tree_hash("one") != before
+
+    def test_mtime_ignored(self, jail_dir):
+        self._make_tree(os.path.join(jail_dir, "one"))
+        jail = Jail(jail_dir)
+        before = jail.tree_hash("one")
+        os.utime(os.path.join(jail_dir, "one", "a.txt"), (0, 0))
+        assert jail.tree_hash("one") == before
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.tree_hash("..")
+
+
+class TestQuotaWriter:
+    """Test streaming writes with a byte quota."""
+
+    def test_within_quota(self, jail_dir):
+        jail = Jail(jail_dir)
+        with jail.quota_writer("out.bin", 10) as out:
+            out.write(b"hello")
+            out.write(bytearray(b"world"))
+            assert out.written == 10
+            assert not os.path.exists(os.path.join(jail_dir, "out.bin"))
+        assert Path(jail_dir, "out.bin").read_bytes() == b"helloworld"
+
+    def test_exceeding_quota_removes_partial(self, jail_dir):
+        Path(jail_dir, "out.bin").write_bytes(b"old")
+        jail = Jail(jail_dir)
+        out = jail.quota_writer("out.bin", 4)
+        out.write(b"abc")
+        with pytest.raises(QuotaExceededError, match="quota of 4 bytes"):
+            out.write(b"de")
+        with pytest.raises(ValueError, match="closed"):
+            out.write(b"x")
+        assert os.listdir(jail_dir) == ["out.bin"]
+        assert Path(jail_dir, "out.bin").read_bytes() == b"old"
+
+    def test_exception_discards(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(RuntimeError):
+            with jail.quota_writer("out.bin", 10) as out:
+                out.write(b"partial")
+                raise RuntimeError("boom")
+        assert os.listdir(jail_dir) == []
+
+    def test_is_value_error(self):
+        assert issubclass(QuotaExceededError, ValueError)
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.quota_writer("../out.bin", 10)
+
+
+class TestFilesDiffer:
+    """Test byte-level file comparison."""
+
+    def test_identical(self, jail_dir):
+        Path(jail_dir, "a.bin").write_bytes(b"x" * 200_000)
+        Path(jail_dir, "b.bin").write_bytes(b"x" * 200_000)
+        assert not Jail(jail_dir).files_differ("a.bin", "b.bin")
+
+    def test_same_size_different_content(self, jail_dir):
+        Path(jail_dir, "a.bin").write_bytes(b"x" * 199_999 + b"y")
+        Path(jail_dir, "b.bin").write_bytes(b"x" * 200_000)
+        assert Jail(jail_dir).files_differ("a.bin", "b.bin")
+
+    def test_different_size(self, jail_dir):
+        Path(jail_dir, "a.bin").write_bytes(b"abc")
+        Path(jail_dir, "b.bin").write_bytes(b"abcd")
+        assert Jail(jail_dir).files_differ("a.bin", "b.bin")
+
+    def test_missing_raises(self, jail_dir):
+        Path(jail_dir, "a.bin").write_bytes(b"abc")
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).files_differ("a.bin", "missing.bin")
+
+    def test_escape_raises(self, jail_dir):
+        Path(jail_dir, "a.bin").write_bytes(b"abc")
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).files_differ("a.bin", "../b.bin")
+
+
+class TestOpenRevalidating:
+    """Test accessors that re-validate on every operation."""
+
+    def test_read_write_append(self, jail_dir):
+        jail = Jail(jail_dir)
+        state = jail.open_revalidating("state.txt")
+        state.write(b"one")
+        state.append(bytearray(b"two"))
+        assert state.read() == b"onetwo"
+        assert paths_equal(state.path, os.path.join(jail_dir, "state.txt"))
+
+    @skip_symlinks_on_windows
+    def test_swapped_directory_rejected(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "sessions"))
+        Path(jail_dir, "sessions", "state.txt").write_bytes(b"ok")
+        outside = tempfile.mkdtemp()
+        Path(outside, "state.txt").write_bytes(b"secret")
+        jail = Jail(jail_dir)
+        state = jail.open_revalidating("sessions/state.txt")
+        assert state.read() == b"ok"
+        os.rename(os.path.join(jail_dir, "sessions"), os.path.join(jail_dir, "old"))
+        os.symlink(outside, os.path.join(jail_dir, "sessions"))
+        with pytest.raises(ValueError, match="escapes"):
+            state.read()
+        with pytest.raises(ValueError, match="escapes"):
+            state.write(b"x")
+        assert Path(outside, "state.txt").read_bytes() == b"secret"
+
+    def test_protected_write_refused(self, jail_dir):
+        jail = Jail(jail_dir, protected=["_system"])
+        state = jail.open_revalidating("_system/state.txt")
+        with pytest.raises(ProtectedPathError):
+            state.write(b"x")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.open_revalidating("../state.txt")
+
+
+class TestDedupMap:
+    """Test bulk canonicalization of path aliases."""
+
+    def test_lexical_aliases_collapse(self, jail_dir):
+        jail = Jail(jail_dir)
+        result = jail.dedup_map(["docs/a.txt", "docs/./a.txt", "docs/x/../a.txt"])
+        assert set(result.values()) == {"docs/a.txt"}
+        assert len(result) == 3
+
+    @skip_symlinks_on_windows
+    def test_symlink_aliases_collapse(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "docs"))
+        Path(jail_dir, "docs", "a.txt").touch()
+        os.symlink("docs", os.path.join(jail_dir, "latest"))
+        jail = Jail(jail_dir)
+        assert jail.dedup_map(["latest/a.txt"]) == {"latest/a.txt": "docs/a.txt"}
+
+    def test_case_and_unicode_normalization(self, jail_dir):
+        jail = Jail(jail_dir)
+        nfd = "cafe\u0301.txt"
+        result = jail.dedup_map(["Docs/A.txt", nfd], case_insensitive=True, unicode_form="NFC")
+        assert result == {"Docs/A.txt": "docs/a.txt", nfd: "caf\u00e9.txt"}
+
+    def test_unknown_unicode_form(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="unicode_form"):
+            jail.dedup_map(["a"], unicode_form="NFX")
+
+    def test_escape_raises_or_maps_to_none(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.dedup_map(["a.txt", "../x"])
+        assert jail.dedup_map(["a.txt", "../x"], strict=False) == {"a.txt": "a.txt", "../x": None}
+
+
+class TestObjectKey:
+    """Test object-store key generation."""
+
+    def test_prefix_joined(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.object_key("a/./b.txt", prefix="/backups/host1/") == "backups/host1/a/b.txt"
+        assert jail.object_key("a/b.txt") == "a/b.txt"
+
+    def test_bad_prefix_rejected(self, jail_dir):
+        jail = Jail(jail_dir)
+        for prefix in ["a//b", "a/../b", "./a"]:
+            with pytest.raises(ValueError, match="invalid key prefix"):
+                jail.object_key("a.txt", prefix=prefix)
+
+    def test_problematic_characters(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="problematic character"):
+            jail.object_key("report#1.txt")
+        assert jail.object_key("report#1 {draft}.txt", encode=True) == "report%231 %7Bdraft%7D.txt"
+
+    def test_root_rejected(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="jail root"):
+            jail.object_key(".")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.object_key("../a.txt")
+
+
+class TestIsLocked:
+    """Test non-blocking lock probes."""
+
+    def test_unlocked(self, jail_dir):
+        Path(jail_dir, "a.txt").write_text("a")
+        jail = Jail(jail_dir)
+        assert not jail.is_locked("a.txt")
+        # The probe released its lock
+        with jail.open_locked("a.txt"):
+            pass
+
+    def test_exclusive_lock_detected(self, jail_dir):
+        Path(jail_dir, "a.txt").write_text("a")
+        jail = Jail(jail_dir)
+        with jail.open_locked("a.txt"):
+            assert jail.is_locked("a.txt")
+        assert not jail.is_locked("a.txt")
+
+    def test_shared_lock_detected(self, jail_dir):
+        Path(jail_dir, "a.txt").write_text("a")
+        jail = Jail(jail_dir)
+        with jail.open_locked("a.txt", mode="rb", shared=True):
+            assert jail.is_locked("a.txt")
+
+    def test_missing_raises(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).is_locked("missing.txt")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).is_locked("../a.txt")
+
+
+class TestEntryCount:
+    """Test directory entry counting."""
+
+    def test_counts_entries(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "d", "sub", "deeper"))
+        for i in range(5):
+            Path(jail_dir, "d", f"{i}.txt").touch()
+        jail = Jail(jail_dir)
+        assert jail.entry_count("d") == 6
+
+    def test_empty_and_default_root(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.entry_count() == 0
+        Path(jail_dir, "a.txt").touch()
+        assert jail.entry_count() == 1
+
+    def test_not_a_directory(self, jail_dir):
+        Path(jail_dir, "a.txt").touch()
+        with pytest.raises(OSError):
+            Jail(jail_dir).entry_count("a.txt")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).entry_count("..")
+
+
+class TestUpdate:
+    """Test atomic read-transform-write."""
+
+    def test_transform_applied(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"hello")
+        jail = Jail(jail_dir)
+        jail.update("a.txt", lambda data: data.upper())
+        assert Path(jail_dir, "a.txt").read_bytes() == b"HELLO"
+
+    def test_transform_error_leaves_file(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"hello")
+        jail = Jail(jail_dir)
+
+        def fail(data):
+            raise RuntimeError("boom")
+
+        with pytest.raises(RuntimeError):
+            jail.update("a.txt", fail)
+        with pytest.raises(TypeError):
+            jail.update("a.txt", lambda data: 42)
+        assert os.listdir(jail_dir) == ["a.txt"]
+        assert Path(jail_dir, "a.txt").read_bytes() == b"hello"
+
+    def test_locked_updates_do_not_lose_writes(self, jail_dir):
+        Path(jail_dir, "n.txt").write_bytes(b"0")
+        jail = Jail(jail_dir)
+
+        def bump(data):
+            return str(int(data) + 1).encode()
+
+        def worker():
+            for _ in range(20):
+                jail.update("n.txt", bump, lock=True)
+
+        threads = [threading.Thread(target=worker) for _ in range(4)]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        assert Path(jail_dir, "n.txt").read_bytes() == b"80"
+
+    def test_missing_raises(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).update("missing.txt", lambda data: data)
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).update("../a.txt", lambda data: data)
+
+
+class TestAncestorsStatus:
+    """Test ancestor listing with existence flags."""
+
+    def test_mixed_existence(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "a", "b"))
+        jail = Jail(jail_dir)
+        assert jail.ancestors_status("a/b/c/file.txt") == [
+            ("a", True),
+            (os.path.join("a", "b"), True),
+            (os.path.join("a", "b", "c"), False),
+        ]
+
+    def test_top_level_has_no_ancestors(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.ancestors_status("file.txt") == []
+
+    def test_file_in_path_is_not_a_directory(self, jail_dir):
+        Path(jail_dir, "a").touch()
+        jail = Jail(jail_dir)
+        assert jail.ancestors_status("a/b/file.txt") == [
+            ("a", False),
+            (os.path.join("a", "b"), False),
+        ]
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.ancestors_status("../a/file.txt")
+
+
+class TestRevealTarget:
+    """Test file-manager reveal commands."""
+
+    def test_platform_command(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "exports"))
+        Path(jail_dir, "exports", "report.pdf").touch()
+        target = os.path.join(jail_dir, "exports", "report.pdf")
+        command = Jail(jail_dir).reveal_target("exports/report.pdf")
+        if sys.platform == "win32":
+            assert command[0] == "explorer"
+            assert command[1].startswith("/select,")
+            assert paths_equal(command[1][len("/select,") :], target)
+        elif sys.platform == "darwin":
+            assert command[:2] == ["open", "-R"]
+            assert paths_equal(command[2], target)
+        else:
+            assert command[0] == "xdg-open"
+            assert paths_equal(command[1], os.path.dirname(target))
+
+    def test_missing_raises(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).reveal_target("missing.pdf")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).reveal_target("../etc/passwd")
+
+
+class TestPathId:
+    """Test stable 64-bit path identifiers."""
+
+    def test_documented_hash(self, jail_dir):
+        jail = Jail(jail_dir)
+        expected = int.from_bytes(hashlib.sha256(b"docs/a.txt").digest()[:8], "big")
+        assert jail.path_id("docs/a.txt") == expected
+
+    def test_aliases_share_id(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.path_id("docs/./a.txt") == jail.path_id("docs/x/../a.txt")
+        assert jail.path_id("docs/a.txt") != jail.path_id("docs/b.txt")
+
+    def test_normalization_options(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.path_id("A.txt", case_insensitive=True) == jail.path_id("a.txt")
+        nfd, nfc = "cafe\u0301", "caf\u00e9"
+        assert jail.path_id(nfd, unicode_form="NFC") == jail.path_id(nfc)
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).path_id("../a.txt")
+
+
+class TestReadJsonl:
+    """Test streaming JSON Lines reads."""
+
+    def test_yields_records(self, jail_dir):
+        with open(os.path.join(jail_dir, "events.jsonl"), "w") as f:
+            f.write('{"a": 1}\n\n[2, 3]\n"x"')
+        assert list(Jail(jail_dir).read_jsonl("events.jsonl")) == [{"a": 1}, [2, 3], "x"]
+
+    def test_malformed_line_reports_number(self, jail_dir):
+        with open(os.path.join(jail_dir, "bad.jsonl"), "w") as f:
+            f.write('{"a": 1}\n{oops\n')
+        records = Jail(jail_dir).read_jsonl("bad.jsonl")
+        assert next(records) == {"a": 1}
+        with pytest.raises(ValueError, match="line 2"):
+            next(records)
+
+    def test_missing_file(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).read_jsonl("missing.jsonl")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).read_jsonl("../events.jsonl")
+
+
+class TestWriteJsonl:
+    """Test appending JSON Lines records."""
+
+    def test_appends_records(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.write_jsonl("log.jsonl", [{"n": 1}]) == 1
+        assert jail.write_jsonl("log.jsonl", ({"n": i} for i in (2, 3))) == 2
+        assert list(jail.read_jsonl("log.jsonl")) == [{"n": 1}, {"n": 2}, {"n": 3}]
+
+    def test_bad_object_writes_nothing(self, jail_dir):
+        jail = Jail(jail_dir)
+        jail.write_jsonl("log.jsonl", [{"n": 1}])
+        with pytest.raises(TypeError):
+            jail.write_jsonl("log.jsonl", [{"n": 2}, object()])
+        assert list(jail.read_jsonl("log.jsonl")) == [{"n": 1}]
+
+    def test_circular_record_writes_nothing(self, jail_dir):
+        jail = Jail(jail_dir)
+        jail.write_jsonl("log.jsonl", [{"n": 1}])
+        record = {"n": 2}
+        record["self"] = record
+        with pytest.raises(ValueError, match="Circular reference"):
+            jail.write_jsonl("log.jsonl", [{"n": 3}, record])
+        assert list(jail.read_jsonl("log.jsonl")) == [{"n": 1}]
+
+    def test_concurrent_large_records_do_not_interleave(self, jail_dir):
+        jail = Jail(jail_dir)
+        # Well over PIPE_BUF, so only the lock keeps the lines whole
+        records = [[{"w": w, "pad": "x" * 100_000}] * 4 for w in range(4)]
+
+        def worker(batch):
+            for _ in range(5):
+                jail.write_jsonl("log.jsonl", batch)
+
+        threads = [threading.Thread(target=worker, args=(batch,)) for batch in records]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        lines = list(jail.read_jsonl("log.jsonl"))
+        assert len(lines) == 80
+        assert all(line["pad"] == "x" * 100_000 for line in lines)
+
+    def test_protected_raises(self, jail_dir):
+        jail = Jail(jail_dir, protected=["logs"])
+        os.makedirs(os.path.join(jail_dir, "logs"))
+        with pytest.raises(ProtectedPathError):
+            jail.write_jsonl("logs/a.jsonl", [{}])
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).write_jsonl("../log.jsonl", [{}])
+
+
+class TestCanonicalRoot:
+    """Test the symlink-resolved root used for containment checks."""
+
+    def test_resolves_root(self, jail_dir):
+        assert Jail(jail_dir).canonical_root() == os.path.realpath(jail_dir)
+
+    @skip_symlinks_on_windows
+    def test_symlinked_ancestor(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "real", "root", "sub"))
+        open(os.path.join(jail_dir, "real", "root", "sub", "f.txt"), "w").close()
+        os.symlink(os.path.join(jail_dir, "real"), os.path.join(jail_dir, "alias"))
+        jail = Jail(os.path.join(jail_dir, "alias", "root"))
+        canonical = jail.canonical_root()
+        assert canonical == os.path.realpath(os.path.join(jail_dir, "real", "root"))
+        assert jail.join("sub/f.txt") == os.path.join(canonical, "sub", "f.txt")
+        via_alias = os.path.join(jail_dir, "alias", "root", "sub", "f.txt")
+        assert jail.contains(via_alias) == os.path.join(canonical, "sub", "f.txt")
+        with pytest.raises(ValueError, match="escapes"):
+            jail.join("../../real/root/sub/f.txt")
+
+
+class TestWriteWouldEscape:
+    """Test pre-flighting writes through symlinks."""
+
+    def test_plain_paths(self, jail_dir):
+        jail = Jail(jail_dir)
+        open(os.path.join(jail_dir, "a.txt"), "w").close()
+        assert jail.write_would_escape("a.txt") is False
+        assert jail.write_would_escape("new/b.txt") is False
+
+    @skip_symlinks_on_windows
+    def test_symlink_out(self, jail_dir):
+        with tempfile.TemporaryDirectory() as outside:
+            open(os.path.join(outside, "target.txt"), "w").close()
+            os.symlink(os.path.join(outside, "target.txt"), os.path.join(jail_dir, "file"))
+            os.symlink(outside, os.path.join(jail_dir, "dir"))
+            jail = Jail(jail_dir)
+            assert jail.write_would_escape("file") is True
+            assert jail.write_would_escape("dir/new.txt") is True
+
+    @skip_symlinks_on_windows
+    def test_dangling_symlink(self, jail_dir):
+        with tempfile.TemporaryDirectory() as outside:
+            os.symlink(os.path.join(outside, "gone"), os.path.join(jail_dir, "out"))
+            os.symlink("missing.txt", os.path.join(jail_dir, "in"))
+            jail = Jail(jail_dir)
+            assert jail.write_would_escape("out") is True
+            assert jail.write_would_escape("in") is False
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).write_would_escape("../a.txt")
+
+
+class TestRequireWritable:
+    """Test the construction-time writability check."""
+
+    def test_writable_root(self, jail_dir):
+        jail = Jail(jail_dir, require_writable=True)
+        assert os.listdir(jail_dir) == []
+        assert paths_equal(jail.root, jail_dir)
+
+    @pytest.mark.skipif(
+        sys.platform == "win32" or os.geteuid() == 0,
+        reason="Directory permissions are not enforced for root or on Windows",
+    )
+    def test_read_only_root_raises(self, jail_dir):
+        os.chmod(jail_dir, 0o555)
+        try:
+            with pytest.raises(PermissionError, match="not writable"):
+                Jail(jail_dir, require_writable=True)
+            Jail(jail_dir)
+        finally:
+            os.chmod(jail_dir, 0o755)
+
+    def test_missing_root_raises(self, jail_dir):
+        with pytest.raises(OSError):
+            Jail(os.path.join(jail_dir, "missing"), require_writable=True)
+
+
+class TestAppendWriter:
+    """Test buffered appends with periodic flushes."""
+
+    def test_flushes_every_n_writes(self, jail_dir):
+        target = os.path.join(jail_dir, "audit.log")
+        writer = Jail(jail_dir).append_writer("audit.log", flush_every=2)
+        writer.write("a\n")
+        assert Path(target).read_bytes() == b""
+        writer.write(b"b\n")
+        assert Path(target).read_bytes() == b"a\nb\n"
+        writer.write("c\n")
+        writer.flush()
+        assert Path(target).read_bytes() == b"a\nb\nc\n"
+        writer.close()
+
+    def test_exit_flushes_and_appends(self, jail_dir):
+        target = os.path.join(jail_dir, "audit.log")
+        Path(target).write_bytes(b"old\n")
+        with Jail(jail_dir).append_writer("audit.log") as writer:
+            writer.write("new\n")
+        assert Path(target).read_bytes() == b"old\nnew\n"
+        with pytest.raises(ValueError, match="closed"):
+            writer.write("late\n")
+
+    def test_invalid_flush_every(self, jail_dir):
+        with pytest.raises(ValueError, match="flush_every"):
+            Jail(jail_dir).append_writer("audit.log", flush_every=0)
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).append_writer("../audit.log")
+        assert not os.path.exists(os.path.join(os.path.dirname(jail_dir), "audit.log"))
+
+
+class TestGroupByDir:
+    """Test grouping validated paths by parent directory."""
+
+    def test_groups_in_first_seen_order(self, jail_dir):
+        groups = Jail(jail_dir).group_by_dir(["a/x.txt", "top.txt", "a/./y.txt", "a/b/z.txt"])
+        assert groups == {
+            "a": ["x.txt", "y.txt"],
+            ".": ["top.txt"],
+            os.path.join("a", "b"): ["z.txt"],
+        }
+        assert list(groups) == ["a", ".", os.path.join("a", "b")]
+
+    def test_empty(self, jail_dir):
+        assert Jail(jail_dir).group_by_dir([]) == {}
+
+    def test_root_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="root"):
+            Jail(jail_dir).group_by_dir(["."])
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).group_by_dir(["a.txt", "../b.txt"])
+
+
+class TestSplitExisting:
+    """Test splitting a path into existing and missing parts."""
+
+    def test_partial(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "a", "b"))
+        prefix, missing = Jail(jail_dir).split_existing("a/b/c/new.txt")
+        assert prefix == os.path.join("a", "b")
+        assert missing == os.path.join("c", "new.txt")
+
+    def test_nothing_exists(self, jail_dir):
+        assert Jail(jail_dir).split_existing("x/y") == (".", os.path.join("x", "y"))
+
+    def test_fully_exists(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "a"))
+        open(os.path.join(jail_dir, "a", "f.txt"), "w").close()
+        assert Jail(jail_dir).split_existing("a/./f.txt") == (os.path.join("a", "f.txt"), "")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).split_existing("../a/b")
+
+
+class TestRedact:
+    """Test redaction-safe path formatting."""
+
+    def test_basename_by_default(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.redact("tenants/acme/invoice.pdf") == "invoice.pdf"
+        assert jail.redact(".") == "."
+
+    def test_hash_policy(self, jail_dir):
+        jail = Jail(jail_dir, redaction="hash")
+        digest = hashlib.sha256(b"tenants").hexdigest()[:8]
+        assert jail.redact("tenants/./invoice.pdf") == f"{digest}/invoice.pdf"
+
+    def test_relative_policy(self, jail_dir):
+        jail = Jail(jail_dir, redaction="relative")
+        assert jail.redact("tenants/x/../invoice.pdf") == "tenants/invoice.pdf"
+        assert jail_dir not in jail.redact("tenants/invoice.pdf")
+
+    def test_unknown_policy(self, jail_dir):
+        with pytest.raises(ValueError, match="unknown redaction"):
+            Jail(jail_dir, redaction="partial")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).redact("../secret.txt")
+
+
+class TestJoinCi:
+    """Test case-insensitive on-disk lookup."""
+
+    def test_returns_real_case(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "Photos"))
+        open(os.path.join(jail_dir, "Photos", "Cat.jpg"), "w").close()
+        result = Jail(jail_dir).join_ci("photos/./CAT.JPG")
+        assert paths_equal(result, os.path.join(jail_dir, "Photos", "Cat.jpg"))
+        assert os.path.basename(result) == "Cat.jpg"
+
+    def test_no_match(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).join_ci("missing.txt")
+
+    def test_ambiguous(self, jail_dir):
+        open(os.path.join(jail_dir, "Report.pdf"), "w").close()
+        if os.path.exists(os.path.join(jail_dir, "report.PDF")):
+            return  # Case-insensitive filesystem; no ambiguity possible
+        open(os.path.join(jail_dir, "report.pdf"), "w").close()
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="ambiguous"):
+            jail.join_ci("REPORT.pdf")
+        assert os.path.basename(jail.join_ci("report.pdf")) == "report.pdf"
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).join_ci("../etc/passwd")
+
+
+class TestReplaceDirContents:
+    """Test atomic directory publishing."""
+
+    def test_swaps_and_removes_old(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "site", "old"))
+        Path(jail_dir, "site", "old", "a.txt").write_text("old")
+        os.makedirs(os.path.join(jail_dir, "staging"))
+        Path(jail_dir, "staging", "b.txt").write_text("new")
+        Jail(jail_dir).replace_dir_contents("site", "staging")
+        assert os.listdir(os.path.join(jail_dir, "site")) == ["b.txt"]
+        assert sorted(os.listdir(jail_dir)) == ["site"]
+
+    def test_missing_target_is_renamed(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "staging"))
+        Path(jail_dir, "staging", "b.txt").write_text("new")
+        Jail(jail_dir).replace_dir_contents("site", "staging")
+        assert Path(jail_dir, "site", "b.txt").read_text() == "new"
+        assert not os.path.exists(os.path.join(jail_dir, "staging"))
+
+    def test_rejects_nested_and_non_directories(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "site", "next"))
+        Path(jail_dir, "file.txt").write_text("x")
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="contain"):
+            jail.replace_dir_contents("site", "site/next")
+        with pytest.raises(OSError):
+            jail.replace_dir_contents("site", "file.txt")
+        assert os.path.isdir(os.path.join(jail_dir, "site", "next"))
+
+    def test_protected_raises(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "site"))
+        os.makedirs(os.path.join(jail_dir, "staging"))
+        jail = Jail(jail_dir, protected=["site"])
+        with pytest.raises(ProtectedPathError):
+            jail.replace_dir_contents("site", "staging")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).replace_dir_contents("site", "../staging")
+
+
+class TestOpenDecompressed:
+    """Test transparent decompression with format sniffing."""
+
+    def test_detects_formats(self, jail_dir):
+        data = b"line\n" * 1000
+        Path(jail_dir, "a.gz").write_bytes(gzip.compress(data))
+        Path(jail_dir, "a.bz2").write_bytes(bz2.compress(data))
+        Path(jail_dir, "a.txt").write_bytes(data)
+        jail = Jail(jail_dir)
+        for name, expected in [("a.gz", "gzip"), ("a.bz2", "bzip2"), ("a.txt", None)]:
+            reader, fmt = jail.open_decompressed(name)
+            assert fmt == expected
+            with reader:
+                assert reader.read() == data
+
+    def test_zstd_frame(self, jail_dir):
+        # A single-segment frame holding one raw block
+        frame = b"\x28\xb5\x2f\xfd\x20\x05\x29\x00\x00hello"
+        Path(jail_dir, "a.zst").write_bytes(frame)
+        reader, fmt = Jail(jail_dir).open_decompressed("a.zst")
+        assert fmt == "zstd"
+        assert reader.read() == b"hello"
+
+    def test_chunked_reads(self, jail_dir):
+        Path(jail_dir, "a.gz").write_bytes(gzip.compress(b"abcdef"))
+        reader, _ = Jail(jail_dir).open_decompressed("a.gz")
+        assert reader.read(4) == b"abcd"
+        assert reader.read(4) == b"ef"
+        assert reader.read(4) == b""
+        reader.close()
+        with pytest.raises(ValueError, match="closed"):
+            reader.read()
+
+    def test_corrupt_data_raises(self, jail_dir):
+        Path(jail_dir, "bad.gz").write_bytes(b"\x1f\x8b" + b"\x00" * 20)
+        reader, _ = Jail(jail_dir).open_decompressed("bad.gz")
+        with pytest.raises(OSError):
+            reader.read()
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).open_decompressed("../a.gz")
+
+
+class TestWriteCompressed:
+    """Test atomic compressed writes."""
+
+    def test_round_trips_each_format(self, jail_dir):
+        data = b"row,value\n" * 5000
+        jail = Jail(jail_dir)
+        for fmt in ["gzip", "zstd", "bzip2"]:
+            size = jail.write_compressed(f"out.{fmt}", data, format=fmt)
+            assert size == os.path.getsize(os.path.join(jail_dir, f"out.{fmt}"))
+            assert size < len(data)
+            reader, detected = jail.open_decompressed(f"out.{fmt}")
+            assert detected == fmt
+            assert reader.read() == data
+        assert gzip.decompress(Path(jail_dir, "out.gzip").read_bytes()) == data
+        assert bz2.decompress(Path(jail_dir, "out.bzip2").read_bytes()) == data
+
+    def test_streams_file_like(self, jail_dir):
+        data = os.urandom(300_000)
+        Path(jail_dir, "src.bin").write_bytes(data)
+        jail = Jail(jail_dir)
+        with open(os.path.join(jail_dir, "src.bin"), "rb") as src:
+            jail.write_compressed("src.bin.gz", src, level=1)
+        assert gzip.decompress(Path(jail_dir, "src.bin.gz").read_bytes()) == data
+
+    def test_source_error_leaves_no_file(self, jail_dir):
+        class Broken:
+            def read(self, size):
+                raise RuntimeError("disk on fire")
+
+        jail = Jail(jail_dir)
+        for fmt in ["gzip", "zstd"]:
+            with pytest.raises(RuntimeError, match="disk on fire"):
+                jail.write_compressed("out.bin", Broken(), format=fmt)
+        assert os.listdir(jail_dir) == []
+
+    def test_invalid_format_and_level(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="unknown format"):
+            jail.write_compressed("out.xz", b"data", format="xz")
+        with pytest.raises(ValueError, match="not supported for bzip2"):
+            jail.write_compressed("out.bz2", b"data", format="bzip2", level=0)
+        # Only level 1 is implemented by the zstd encoder
+        jail.write_compressed("out.zst", b"data", format="zstd", level=1)
+        for level in (3, 19):
+            with pytest.raises(ValueError, match="only 1"):
+                jail.write_compressed("out.zst", b"data", format="zstd", level=level)
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).write_compressed("../out.gz", b"data")
+
+
+class TestMatchesIgnore:
+    """Test matching against caller-supplied ignore patterns."""
+
+    def test_patterns_and_negation(self, jail_dir):
+        jail = Jail(jail_dir)
+        spec = ["*.log", "!keep.log", "docs/**/draft-*"]
+        assert jail.matches_ignore("server.log", spec) is True
+        assert jail.matches_ignore("sub/server.log", spec) is True
+        assert jail.matches_ignore("keep.log", spec) is False
+        assert jail.matches_ignore("docs/a/b/draft-1.md", spec) is True
+        assert jail.matches_ignore("docs/final.md", spec) is False
+
+    def test_directory_only_patterns(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "src", "node_modules", "x"))
+        open(os.path.join(jail_dir, "cache"), "w").close()
+        jail = Jail(jail_dir)
+        spec = ["node_modules/", "cache/"]
+        assert jail.matches_ignore("src/node_modules/x/index.js", spec) is True
+        assert jail.matches_ignore("cache", spec) is False
+
+    def test_invalid_pattern(self, jail_dir):
+        with pytest.raises(ValueError, match="invalid ignore pattern"):
+            Jail(jail_dir).matches_ignore("a.txt", ["[z-a].txt"])
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).matches_ignore("../a.log", ["*.log"])
+
+
+class TestVersionToken:
+    """Test version tokens and compare-and-swap updates."""
+
+    def test_token_changes_on_write(self, jail_dir):
+        Path(jail_dir, "doc.md").write_bytes(b"v1")
+        jail = Jail(jail_dir)
+        token = jail.version_token("doc.md")
+        assert token == jail.version_token("./doc.md")
+        Path(jail_dir, "doc.md").write_bytes(b"v2!")
+        assert jail.version_token("doc.md") != token
+
+    def test_update_if_unchanged(self, jail_dir):
+        Path(jail_dir, "doc.md").write_bytes(b"v1")
+        jail = Jail(jail_dir)
+        token = jail.version_token("doc.md")
+        assert jail.update_if_unchanged("doc.md", token, b"v2") is True
+        assert Path(jail_dir, "doc.md").read_bytes() == b"v2"
+        # The write itself changed the token, so the stale one is rejected
+        assert jail.update_if_unchanged("doc.md", token, b"v3") is False
+        assert Path(jail_dir, "doc.md").read_bytes() == b"v2"
+
+    def test_missing_file(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).version_token("missing.md")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.version_token("../doc.md")
+        with pytest.raises(ValueError, match="escapes"):
+            jail.update_if_unchanged("../doc.md", 0, b"x")
+
+
+class TestWriteIfChanged:
+    """Test writes that skip unchanged content."""
+
+    def test_creates_missing_file(self, jail_dir):
+        assert Jail(jail_dir).write_if_changed("out.txt", b"hello") is True
+        assert Path(jail_dir, "out.txt").read_bytes() == b"hello"
+
+    def test_skips_identical_content(self, jail_dir):
+        target = Path(jail_dir, "out.txt")
+        target.write_bytes(b"hello")
+        os.utime(target, (1_000_000, 1_000_000))
+        assert Jail(jail_dir).write_if_changed("out.txt", bytearray(b"hello")) is False
+        assert target.stat().st_mtime == 1_000_000
+
+    def test_rewrites_different_content(self, jail_dir):
+        target = Path(jail_dir, "out.txt")
+        target.write_bytes(b"hello")
+        jail = Jail(jail_dir)
+        assert jail.write_if_changed("out.txt", b"hellO") is True
+        assert jail.write_if_changed("out.txt", b"hi") is True
+        assert target.read_bytes() == b"hi"
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).write_if_changed("../out.txt", b"x")
+
+
+class TestWriteBatch:
+    """Test all-or-nothing multi-file writes."""
+
+    def test_writes_all(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"old")
+        Jail(jail_dir).write_batch([("a.txt", b"new"), ("b.txt", bytearray(b"b"))])
+        assert Path(jail_dir, "a.txt").read_bytes() == b"new"
+        assert Path(jail_dir, "b.txt").read_bytes() == b"b"
+        assert sorted(os.listdir(jail_dir)) == ["a.txt", "b.txt"]
+
+    def test_rolls_back_on_failure(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"old")
+        os.makedirs(os.path.join(jail_dir, "dir", "child"))
+        jail = Jail(jail_dir)
+        with pytest.raises(OSError):
+            jail.write_batch([("a.txt", b"new"), ("b.txt", b"b"), ("dir", b"boom")])
+        assert Path(jail_dir, "a.txt").read_bytes() == b"old"
+        assert sorted(os.listdir(jail_dir)) == ["a.txt", "dir"]
+
+    def test_staging_failure_changes_nothing(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"old")
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).write_batch([("a.txt", b"new"), ("missing/b.txt", b"b")])
+        assert Path(jail_dir, "a.txt").read_bytes() == b"old"
+        assert os.listdir(jail_dir) == ["a.txt"]
+
+    def test_escape_fails_whole_batch(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).write_batch([("a.txt", b"a"), ("../b.txt", b"b")])
+        assert os.listdir(jail_dir) == []
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Block counts are Unix only")
+class TestAllocatedSize:
+    """Test physical (allocated) file sizes."""
+
+    def test_sparse_file(self, jail_dir):
+        with open(os.path.join(jail_dir, "disk.img"), "wb") as f:
+            f.truncate(64 * 1024 * 1024)
+        jail = Jail(jail_dir)
+        assert jail.allocated_size("disk.img") < 64 * 1024 * 1024
+        assert jail.allocated_size("disk.img") == os.stat(jail.join("disk.img")).st_blocks * 512
+
+    def test_missing_file(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).allocated_size("missing.img")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).allocated_size("../disk.img")
+
+
+class TestTempSibling:
+    """Test temporary sibling names."""
+
+    def test_same_directory_and_unused(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "db"))
+        jail = Jail(jail_dir)
+        tmp = jail.temp_sibling("db/snapshot.bin")
+        directory, name = os.path.split(tmp)
+        assert directory == "db"
+        assert name.startswith(".tmp-") and name.endswith("-snapshot.bin")
+        assert not os.path.exists(os.path.join(jail_dir, tmp))
+        assert jail.temp_sibling("db/snapshot.bin") != tmp
+
+    def test_nothing_created(self, jail_dir):
+        Jail(jail_dir).temp_sibling("a.txt")
+        assert os.listdir(jail_dir) == []
+
+    def test_root_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="root"):
+            Jail(jail_dir).temp_sibling(".")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).temp_sibling("../a.txt")
+
+
+class TestListTree:
+    """Test depth-limited subtree listings."""
+
+    def _make_tree(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "p", "a", "deep"))
+        open(os.path.join(jail_dir, "p", "a", "deep", "x.txt"), "w").close()
+        open(os.path.join(jail_dir, "p", "a", "f.txt"), "w").close()
+        open(os.path.join(jail_dir, "p", "top.txt"), "w").close()
+
+    def test_depth_counts_from_start(self, jail_dir):
+        self._make_tree(jail_dir)
+        jail = Jail(jail_dir)
+        j = os.path.join
+        assert jail.list_tree("p") == [j("p", "a"), j("p", "top.txt")]
+        assert jail.list_tree("p", max_depth=2) == [
+            j("p", "a"),
+            j("p", "a", "deep"),
+            j("p", "a", "f.txt"),
+            j("p", "top.txt"),
+        ]
+        assert len(jail.list_tree("p", max_depth=10)) == 5
+        assert jail.list_tree("p", max_depth=0) == []
+
+    @skip_symlinks_on_windows
+    def test_escaping_symlinks_pruned(self, jail_dir):
+        os.makedirs(os.path.join(jail_dir, "p"))
+        with tempfile.TemporaryDirectory() as outside:
+            os.symlink(outside, os.path.join(jail_dir, "p", "out"))
+            os.symlink("../p", os.path.join(jail_dir, "p", "loop"))
+            assert Jail(jail_dir).list_tree("p", max_depth=3) == [os.path.join("p", "loop")]
+
+    def test_not_a_directory(self, jail_dir):
+        open(os.path.join(jail_dir, "f.txt"), "w").close()
+        with pytest.raises(OSError):
+            Jail(jail_dir).list_tree("f.txt")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).list_tree("..")
+
+
+@pytest.mark.skipif(sys.platform == "win32", reason="Owners are Unix only")
+class TestOwnerGroup:
+    """Test owner and group name lookups."""
+
+    def test_matches_pwd_and_grp(self, jail_dir):
+        import grp
+        import pwd
+
+        open(os.path.join(jail_dir, "a.txt"), "w").close()
+        st = os.stat(os.path.join(jail_dir, "a.txt"))
+        jail = Jail(jail_dir)
+        assert jail.owner("a.txt") == pwd.getpwuid(st.st_uid).pw_name
+        assert jail.group("a.txt") == grp.getgrgid(st.st_gid).gr_name
+        # Served from the cache the second time
+        assert jail.owner("a.txt") == pwd.getpwuid(st.st_uid).pw_name
+
+    def test_missing_file(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).owner("missing.txt")
+
+    def test_escape_raises(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.owner("../a.txt")
+        with pytest.raises(ValueError, match="escapes"):
+            jail.group("../a.txt")
+
+
+class TestLineCount:
+    """Test newline counting."""
+
+    def test_counts_newlines(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"one\ntwo\nthree\n")
+        assert Jail(jail_dir).line_count("a.txt") == 3
+
+    def test_last_line_without_newline_not_counted(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"one\ntwo")
+        assert Jail(jail_dir).line_count("a.txt") == 1
+        Path(jail_dir, "empty.txt").write_bytes(b"")
+        assert Jail(jail_dir).line_count("empty.txt") == 0
+
+    def test_spans_chunks(self, jail_dir):
+        Path(jail_dir, "big.txt").write_bytes((b"y" * 99 + b"\n") * 2001)
+        assert Jail(jail_dir).line_count("big.txt") == 2001
+
+    def test_missing_file(self, jail_dir):
+        with pytest.raises(FileNotFoundError):
+            Jail(jail_dir).line_count("missing.txt")
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).line_count("../a.txt")
+
+
+class TestIsText:
+    """Test text/binary classification."""
+
+    def test_text_and_binary(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"hello\tworld\r\n" * 10)
+        Path(jail_dir, "a.bin").write_bytes(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR")
+        Path(jail_dir, "empty").write_bytes(b"")
+        jail = Jail(jail_dir)
+        assert jail.is_text("a.txt") is True
+        assert jail.is_text("a.bin") is False
+        assert jail.is_text("empty") is True
+
+    def test_sample_limits_bytes_inspected(self, jail_dir):
+        Path(jail_dir, "a.dat").write_bytes(b"x" * 100 + b"\x00")
+        jail = Jail(jail_dir)
+        assert jail.is_text("a.dat", sample=100) is True
+        assert jail.is_text("a.dat", sample=101) is False
+
+    def test_control_ratio_and_utf8(self, jail_dir):
+        Path(jail_dir, "ctl").write_bytes(b"ab\x01\x02")
+        Path(jail_dir, "latin1").write_bytes("café au lait".encode("latin-1"))
+        Path(jail_dir, "utf8").write_bytes("café".encode() * 3000)
+        jail = Jail(jail_dir)
+        assert jail.is_text("ctl") is False
+        assert jail.is_text("ctl", max_control_ratio=0.5) is True
+        assert jail.is_text("latin1") is True
+        assert jail.is_text("latin1", require_utf8=True) is False
+        # A sample that cuts a multi-byte character in half is still UTF-8
+        assert jail.is_text("utf8", sample=4, require_utf8=True) is True
+
+    def test_invalid_arguments(self, jail_dir):
+        Path(jail_dir, "a.txt").write_bytes(b"abc")
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="sample"):
+            jail.is_text("a.txt", sample=0)
+        with pytest.raises(ValueError, match="max_control_ratio"):
+            jail.is_text("a.txt", max_control_ratio=1.5)
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).is_text("../a.txt")
+
+
+class TestAppendCapped:
+    """Test appending with size-based rotation."""
+
+    def test_rotate_before(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.append_capped("app.log", b"aaaa\n", max_bytes=10) is False
+        assert jail.append_capped("app.log", b"bbbb\n", max_bytes=10) is False
+        # A third record would exceed the cap, so it starts a fresh file
+        assert jail.append_capped("app.log", b"cccc\n", max_bytes=10) is True
+        assert Path(jail_dir, "app.log").read_bytes() == b"cccc\n"
+        assert Path(jail_dir, "app.log.1").read_bytes() == b"aaaa\nbbbb\n"
+
+    def test_rotate_after(self, jail_dir):
+        jail = Jail(jail_dir)
+        assert jail.append_capped("app.log", b"aaaaaa\n", max_bytes=10, rotate="after") is False
+        # This record takes the file past the cap, so it is rotated out with it
+        assert jail.append_capped("app.log", b"bbbbbb\n", max_bytes=10, rotate="after") is True
+        assert not Path(jail_dir, "app.log").exists()
+        assert Path(jail_dir, "app.log.1").read_bytes() == b"aaaaaa\nbbbbbb\n"
+
+    def test_keep_prunes_oldest(self, jail_dir):
+        jail = Jail(jail_dir)
+        for i in range(5):
+            jail.append_capped("app.log", b"%d" % i * 8, max_bytes=10, keep=2)
+        assert Path(jail_dir, "app.log").read_bytes() == b"4" * 8
+        assert Path(jail_dir, "app.log.1").read_bytes() == b"3" * 8
+        assert Path(jail_dir, "app.log.2").read_bytes() == b"2" * 8
+        assert not Path(jail_dir, "app.log.3").exists()
+
+        jail.append_capped("other.log", b"x" * 8, max_bytes=10, keep=0)
+        jail.append_capped("other.log", b"y" * 8, max_bytes=10, keep=0)
+        assert Path(jail_dir, "other.log").read_bytes() == b"y" * 8
+        assert not Path(jail_dir, "other.log.1").exists()
+
+    def test_invalid_arguments(self, jail_dir):
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="rotate"):
+            jail.append_capped("app.log", b"x", max_bytes=10, rotate="never")
+        with pytest.raises(ValueError, match="max_bytes"):
+            jail.append_capped("app.log", b"x", max_bytes=0)
+        with pytest.raises(ValueError, match="keep"):
+            jail.append_capped("app.log", b"x", max_bytes=10, keep=10**9)
+        with pytest.raises(ValueError, match="jail root"):
+            jail.append_capped(".", b"x", max_bytes=10)
+
+    @skip_symlinks_on_windows
+    def test_symlinked_backup_slot_is_not_followed(self, jail_dir):
+        Path(jail_dir, "config.yaml").write_bytes(b"keep me")
+        Path(jail_dir, "app.log").write_bytes(b"x" * 8)
+        os.symlink("config.yaml", os.path.join(jail_dir, "app.log.1"))
+        assert Jail(jail_dir).append_capped("app.log", b"y" * 8, max_bytes=10, keep=1)
+        # The link was replaced by the rotated log; its target is untouched
+        assert Path(jail_dir, "config.yaml").read_bytes() == b"keep me"
+        assert not os.path.islink(os.path.join(jail_dir, "app.log.1"))
+        assert Path(jail_dir, "app.log.1").read_bytes() == b"x" * 8
+        assert Path(jail_dir, "app.log").read_bytes() == b"y" * 8
+
+    @skip_symlinks_on_windows
+    def test_symlinked_middle_slot_moves_as_link(self, jail_dir):
+        outside = tempfile.NamedTemporaryFile(delete=False)
+        outside.write(b"outside")
+        outside.close()
+        try:
+            Path(jail_dir, "app.log").write_bytes(b"x" * 8)
+            os.symlink(outside.name, os.path.join(jail_dir, "app.log.1"))
+            Jail(jail_dir).append_capped("app.log", b"y" * 8, max_bytes=10, keep=2)
+            assert os.readlink(os.path.join(jail_dir, "app.log.2")) == outside.name
+            assert Path(outside.name).read_bytes() == b"outside"
+        finally:
+            os.unlink(outside.name)
+
+    @skip_symlinks_on_windows
+    def test_symlinked_target_raises(self, jail_dir):
+        Path(jail_dir, "real.log").write_bytes(b"x" * 8)
+        os.symlink("real.log", os.path.join(jail_dir, "app.log"))
+        os.symlink("missing.log", os.path.join(jail_dir, "dangling.log"))
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="symlink"):
+            jail.append_capped("app.log", b"y" * 8, max_bytes=10)
+        with pytest.raises(ValueError, match="symlink"):
+            jail.append_capped("dangling.log", b"y", max_bytes=10)
+        # Nothing was written, locked or rotated
+        assert Path(jail_dir, "real.log").read_bytes() == b"x" * 8
+        assert sorted(os.listdir(jail_dir)) == ["app.log", "dangling.log", "real.log"]
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).append_capped("../app.log", b"x", max_bytes=10)
+
+
+class TestShardedCounter:
+    """Test counters sharded across several files."""
+
+    def test_incr_and_total(self, jail_dir):
+        counter = Jail(jail_dir).sharded_counter("hits", shards=4)
+        assert counter.total() == 0
+        for _ in range(20):
+            counter.incr()
+        counter.incr(5)
+        assert counter.total() == 25
+        assert set(os.listdir(os.path.join(jail_dir, "hits"))) <= {"0", "1", "2", "3"}
+
+    def test_concurrent_increments(self, jail_dir):
+        counter = Jail(jail_dir).sharded_counter("hits", shards=4)
+
+        def worker():
+            for _ in range(50):
+                counter.incr()
+
+        threads = [threading.Thread(target=worker) for _ in range(8)]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        assert counter.total() == 400
+
+    def test_reopen_sees_existing_counts(self, jail_dir):
+        jail = Jail(jail_dir)
+        jail.sharded_counter("hits").incr(3)
+        assert jail.sharded_counter("hits").total() == 3
+
+    def test_invalid_shards(self, jail_dir):
+        with pytest.raises(ValueError, match="shards"):
+            Jail(jail_dir).sharded_counter("hits", shards=0)
+
+    @skip_symlinks_on_windows
+    def test_escaping_shard_raises(self, jail_dir):
+        counter = Jail(jail_dir).sharded_counter("hits", shards=1)
+        os.symlink("/etc/passwd", os.path.join(jail_dir, "hits", "0"))
+        with pytest.raises(ValueError, match="escapes"):
+            counter.incr()
+
+    def test_escape_raises(self, jail_dir):
+        with pytest.raises(ValueError, match="escapes"):
+            Jail(jail_dir).sharded_counter("../hits")
+
+
+class TestTransformFile:
+    """Test streaming a file through a transform."""
+
+    def test_chunks_and_final_flush(self, jail_dir):
+        Path(jail_dir, "src.txt").write_bytes(b"abcdefghij")
+        calls = []
+
+        def upper(chunk):
+            calls.append(chunk)
+            return chunk.upper()
+
+        jail = Jail(jail_dir)
+        assert jail.transform_file("src.txt", "dst.txt", upper, chunk_size=4) == 10
+        assert calls == [b"abcd", b"efgh", b"ij", b""]
+        assert Path(jail_dir, "dst.txt").read_bytes() == b"ABCDEFGHIJ"
+
+    def test_transform_changes_sizes_and_holds_state(self, jail_dir):
+        # "\r\n" split across a chunk boundary must still be normalized
+        Path(jail_dir, "data.csv").write_bytes(b"a,b\r\nc,d\r\n")
+        pending = b""
+
+        def crlf_to_lf(chunk):
+            nonlocal pending
+            data = pending + chunk
+            pending = data[-1:] if chunk and data.endswith(b"\r") else b""
+            return data[: len(data) - len(pending)].replace(b"\r\n", b"\n")
+
+        jail = Jail(jail_dir)
+        assert jail.transform_file("data.csv", "data.csv", crlf_to_lf, chunk_size=4) == 8
+        assert Path(jail_dir, "data.csv").read_bytes() == b"a,b\nc,d\n"
+
+    def test_failing_transform_leaves_dst(self, jail_dir):
+        Path(jail_dir, "src.txt").write_bytes(b"new")
+        Path(jail_dir, "dst.txt").write_bytes(b"old")
+
+        def fail(chunk):
+            raise RuntimeError("boom")
+
+        jail = Jail(jail_dir)
+        with pytest.raises(RuntimeError, match="boom"):
+            jail.transform_file("src.txt", "dst.txt", fail)
+        with pytest.raises(TypeError):
+            jail.transform_file("src.txt", "dst.txt", lambda chunk: 42)
+        assert Path(jail_dir, "dst.txt").read_bytes() == b"old"
+        assert sorted(os.listdir(jail_dir)) == ["dst.txt", "src.txt"]
+
+    def test_invalid_chunk_size(self, jail_dir):
+        Path(jail_dir, "src.txt").write_bytes(b"x")
+        with pytest.raises(ValueError, match="chunk_size"):
+            Jail(jail_dir).transform_file("src.txt", "dst.txt", bytes, chunk_size=0)
+
+    def test_escape_raises(self, jail_dir):
+        Path(jail_dir, "src.txt").write_bytes(b"x")
+        jail = Jail(jail_dir)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.transform_file("../src.txt", "dst.txt", bytes)
+        with pytest.raises(ValueError, match="escapes"):
+            jail.transform_file("src.txt", "../dst.txt", bytes)